@@ -19,7 +19,7 @@ use crate::tui;
 pub fn app_main() -> Result<()> {
     errors::install_hooks()?;
     let mut terminal = tui::init()?;
-    App::new(Box::new(UntypedArithmetic)).run(&mut terminal)?;
+    App::new(Box::new(UntypedArithmetic::default())).run(&mut terminal)?;
     tui::restore()?;
     Ok(())
 }
@@ -163,7 +163,7 @@ impl App {
             return;
         }
 
-        let out = self.evaluator.run(&input);
+        let out = evaluator::run_input(self.evaluator.as_mut(), &input);
         self.history_output.push(out);
     }
 
@@ -0,0 +1,172 @@
+use std::fmt::{self, Display, Formatter};
+
+/// A derivation tree: the conclusion of an inference rule together with
+/// the derivations of its premises.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Derivation {
+    pub rule: &'static str,
+    pub conclusion: String,
+    pub premises: Vec<Derivation>,
+}
+
+impl Derivation {
+    pub fn new(rule: &'static str, conclusion: String, premises: Vec<Derivation>) -> Derivation {
+        Derivation {
+            rule,
+            conclusion,
+            premises,
+        }
+    }
+
+    pub fn axiom(rule: &'static str, conclusion: String) -> Derivation {
+        Derivation::new(rule, conclusion, Vec::new())
+    }
+
+    /// Renders the tree as LaTeX if `latex` is set, as ASCII otherwise.
+    pub fn render(&self, latex: bool) -> String {
+        if latex {
+            self.to_latex()
+        } else {
+            self.to_string()
+        }
+    }
+
+    /// Renders the tree as LaTeX source for the `bussproofs` package.
+    pub fn to_latex(&self) -> String {
+        let mut out = String::from("\\begin{prooftree}\n");
+        self.write_latex(&mut out);
+        out.push_str("\\end{prooftree}\n");
+        out
+    }
+
+    /// bussproofs builds trees bottom-up on a stack: the premises are
+    /// pushed first and the inference pops them.
+    fn write_latex(&self, out: &mut String) {
+        if self.premises.is_empty() {
+            out.push_str("\\AxiomC{}\n");
+        }
+        for premise in &self.premises {
+            premise.write_latex(out);
+        }
+        let inference = match self.premises.len() {
+            0 | 1 => "Unary",
+            2 => "Binary",
+            3 => "Trinary",
+            4 => "Quaternary",
+            5 => "Quinary",
+            n => panic!("bussproofs supports at most 5 premises, got {}", n),
+        };
+        out.push_str(&format!("\\RightLabel{{\\scriptsize {}}}\n", self.rule));
+        out.push_str(&format!("\\{}InfC{{${}$}}\n", inference, latex_math(&self.conclusion)));
+    }
+
+    fn write_ascii(&self, f: &mut Formatter, indent: usize) -> fmt::Result {
+        writeln!(f, "{}{}  ({})", "  ".repeat(indent), self.conclusion, self.rule)?;
+        for premise in &self.premises {
+            premise.write_ascii(f, indent + 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// An indented proof tree: the conclusion first, with the derivations of
+/// its premises below it, one level deeper.
+impl Display for Derivation {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.write_ascii(f, 0)
+    }
+}
+
+/// Builds the derivation of the single step `before → after`, which
+/// contracted the redex at `path` using the computation rule `rule`. Every
+/// index on the path is justified by the congruence rule returned by
+/// `congruence`, with `child` selecting the subterm the step was taken in.
+pub fn small_step<T: Display>(
+    before: &T,
+    after: &T,
+    path: &[usize],
+    rule: &'static str,
+    child: fn(&T, usize) -> &T,
+    congruence: fn(&T, usize) -> &'static str,
+) -> Derivation {
+    let conclusion = format!("{} → {}", before, after);
+    match path.split_first() {
+        None => Derivation::axiom(rule, conclusion),
+        Some((&index, rest)) => {
+            let premise = small_step(
+                child(before, index),
+                child(after, index),
+                rest,
+                rule,
+                child,
+                congruence,
+            );
+            Derivation::new(congruence(before, index), conclusion, vec![premise])
+        }
+    }
+}
+
+/// Translates a judgement written in the REPL's notation to LaTeX math
+/// mode. Keywords and identifiers are set in sans-serif.
+fn latex_math(s: &str) -> String {
+    let mut out = String::new();
+    let mut word = String::new();
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_ascii_alphanumeric() {
+            word.push(c);
+            continue;
+        }
+        if !word.is_empty() {
+            out.push_str(&format!("\\mathsf{{{}}}", word));
+            word.clear();
+        }
+        match c {
+            ' ' => out.push_str("\\ "),
+            'λ' => out.push_str("\\lambda "),
+            '→' => out.push_str("\\to "),
+            '⇓' => out.push_str("\\Downarrow "),
+            '⊢' => out.push_str("\\vdash "),
+            'Γ' => out.push_str("\\Gamma "),
+            '∅' => out.push_str("\\emptyset "),
+            '-' if chars.peek() == Some(&'>') => {
+                chars.next();
+                out.push_str("\\to ");
+            }
+            '_' | '{' | '}' | '#' | '$' | '%' | '&' => {
+                out.push('\\');
+                out.push(c);
+            }
+            c => out.push(c),
+        }
+    }
+    if !word.is_empty() {
+        out.push_str(&format!("\\mathsf{{{}}}", word));
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let d = Derivation::new(
+            "E-Succ",
+            "succ pred 0 → succ 0".to_string(),
+            vec![Derivation::axiom("E-PredZero", "pred 0 → 0".to_string())],
+        );
+        assert_eq!(d.to_string(), "succ pred 0 → succ 0  (E-Succ)\n  pred 0 → 0  (E-PredZero)\n");
+        assert_eq!(
+            d.to_latex(),
+            "\\begin{prooftree}\n\
+             \\AxiomC{}\n\
+             \\RightLabel{\\scriptsize E-PredZero}\n\
+             \\UnaryInfC{$\\mathsf{pred}\\ \\mathsf{0}\\ \\to \\ \\mathsf{0}$}\n\
+             \\RightLabel{\\scriptsize E-Succ}\n\
+             \\UnaryInfC{$\\mathsf{succ}\\ \\mathsf{pred}\\ \\mathsf{0}\\ \\to \\ \\mathsf{succ}\\ \\mathsf{0}$}\n\
+             \\end{prooftree}\n"
+        );
+    }
+}
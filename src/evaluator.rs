@@ -1,14 +1,24 @@
 use std::fmt::{self, Debug, Formatter};
 
+use crate::simply_typed_lambda_calculus::SimplyTypedLambdaCalculus;
+use crate::typed_arithmetic::TypedArithmetic;
 use crate::untyped_arithmetic::UntypedArithmetic;
 use crate::untyped_lambda_calculus::UntypedLambdaCalculus;
 use crate::lisp::Lisp;
 use color_eyre::eyre::Result;
 
 pub trait Evaluator {
-    fn run(&self, input: &str) -> String;
+    fn run(&mut self, input: &str) -> String;
     fn __debug__(&self) -> String;
     fn name(&self) -> String;
+
+    /// Whether typing derivations are printed, or `None` if the language
+    /// has no type system.
+    fn typing(&self) -> Option<bool> {
+        None
+    }
+
+    fn set_typing(&mut self, _typing: bool) {}
 }
 
 impl Debug for dyn Evaluator {
@@ -18,14 +28,81 @@ impl Debug for dyn Evaluator {
 }
 
 pub fn available_evaluators() -> Vec<Box<dyn Evaluator>> {
-    vec![Box::new(UntypedArithmetic), Box::new(UntypedLambdaCalculus), Box::new(Lisp)]
+    vec![
+        Box::new(UntypedArithmetic::default()),
+        Box::new(UntypedLambdaCalculus::default()),
+        Box::new(Lisp::default()),
+        Box::new(TypedArithmetic::default()),
+        Box::new(SimplyTypedLambdaCalculus::default()),
+    ]
 }
 
 pub fn pick(index: usize) -> Result<Box<dyn Evaluator>> {
     match index {
-        1 => return Ok(Box::new(UntypedArithmetic)),
-        2 => return Ok(Box::new(UntypedLambdaCalculus)),
-        3 => return Ok(Box::new(Lisp)),
-        _ => return Err(color_eyre::eyre::eyre!("Invalid evaluator index")),
-    };
+        1 => Ok(Box::new(UntypedArithmetic::default())),
+        2 => Ok(Box::new(UntypedLambdaCalculus::default())),
+        3 => Ok(Box::new(Lisp::default())),
+        4 => Ok(Box::new(TypedArithmetic::default())),
+        5 => Ok(Box::new(SimplyTypedLambdaCalculus::default())),
+        _ => Err(color_eyre::eyre::eyre!("Invalid evaluator index")),
+    }
+}
+
+/// Runs one line of REPL input. Commands shared by several languages, like
+/// `:typing`, are handled here; everything else is up to the evaluator.
+pub fn run_input(evaluator: &mut dyn Evaluator, input: &str) -> String {
+    match (parse_command(input), evaluator.typing()) {
+        (Some(("typing", arg)), Some(current)) => match parse_toggle(arg, current) {
+            Some(typing) => {
+                evaluator.set_typing(typing);
+                format!("typing: {}", if typing { "on" } else { "off" })
+            }
+            None => format!("Error: Expected 'on' or 'off', got '{}'", arg),
+        },
+        _ => evaluator.run(input),
+    }
+}
+
+/// Splits a REPL command such as `:strategy cbv` into its name and argument.
+/// Returns `None` when the input is not a command.
+pub fn parse_command(input: &str) -> Option<(&str, &str)> {
+    let rest = input.strip_prefix(':')?;
+    match rest.split_once(char::is_whitespace) {
+        Some((name, arg)) => Some((name, arg.trim())),
+        None => Some((rest, "")),
+    }
+}
+
+/// Parses the argument of an on/off REPL setting. An empty argument flips
+/// the current value.
+pub fn parse_toggle(arg: &str, current: bool) -> Option<bool> {
+    match arg {
+        "" => Some(!current),
+        "on" => Some(true),
+        "off" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pick() {
+        // Scripts pick evaluators by number, so new ones go at the end.
+        let names = [
+            "Untyped Arithmetic",
+            "Untyped Lambda Calculus",
+            "Lisp",
+            "Typed Arithmetic",
+            "Simply Typed Lambda Calculus",
+        ];
+        let available: Vec<String> = available_evaluators().iter().map(|e| e.name()).collect();
+        assert_eq!(available, names);
+        for (i, name) in names.iter().enumerate() {
+            assert_eq!(pick(i + 1).unwrap().name(), *name);
+        }
+        assert!(pick(names.len() + 1).is_err());
+    }
 }
@@ -1,13 +1,25 @@
+use super::number::Number;
+
 use crate::parsing_utils::Spanned;
 
+use std::rc::Rc;
+
 pub type SExpr = Spanned<Expr>;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(super) enum Expr {
     Symbol(String),
-    Number(i64),
+    Number(Number),
     String(String),
-    List(Vec<SExpr>),
+    Char(char),
+    Bool(bool),
+    /// `#n`, the empty list.
+    Nil,
+    /// Shared, so that the evaluator can hold on to the rest of a form
+    /// cheaply.
+    List(Rc<[SExpr]>),
+    /// `(a b . c)`, a list whose last cdr is `c` rather than the empty list.
+    DottedList(Vec<SExpr>, Box<SExpr>),
     Quote(Box<SExpr>),
 }
 
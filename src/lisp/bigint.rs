@@ -0,0 +1,421 @@
+use std::cmp::Ordering;
+use std::fmt::{self, Display, Formatter};
+use std::ops::{Add, Mul, Neg, Sub};
+
+/// An integer of any size: a sign and a magnitude in base 2^32, least
+/// significant digit first, without leading zero digits. Zero has no digits
+/// and is not negative.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct BigInt {
+    negative: bool,
+    digits: Vec<u32>,
+}
+
+/// Decimal digits are converted nine at a time.
+const CHUNK: u32 = 1_000_000_000;
+
+impl BigInt {
+    fn new(negative: bool, mut digits: Vec<u32>) -> BigInt {
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+        let negative = negative && !digits.is_empty();
+        BigInt { negative, digits }
+    }
+
+    /// `2^exponent`.
+    pub fn pow2(exponent: u32) -> BigInt {
+        let mut digits = vec![0; exponent as usize / 32];
+        digits.push(1 << (exponent % 32));
+        BigInt::new(false, digits)
+    }
+
+    /// Reads an optionally signed decimal integer.
+    pub fn parse(s: &str) -> Option<BigInt> {
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+        if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        let mut magnitude = Vec::new();
+        let first = digits.len() % 9;
+        let chunks = std::iter::once(&digits[..first]).chain(
+            digits.as_bytes()[first..]
+                .chunks(9)
+                .map(|c| std::str::from_utf8(c).unwrap()),
+        );
+        for chunk in chunks.filter(|c| !c.is_empty()) {
+            let scale = 10u32.pow(chunk.len() as u32);
+            mul_small_add(&mut magnitude, scale, chunk.parse().unwrap());
+        }
+        Some(BigInt::new(negative, magnitude))
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.digits.is_empty()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    pub fn abs(&self) -> BigInt {
+        BigInt::new(false, self.digits.clone())
+    }
+
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.digits.len() > 2 {
+            return None;
+        }
+        let magnitude = self
+            .digits
+            .iter()
+            .rev()
+            .fold(0u64, |n, &d| n << 32 | d as u64);
+        if self.negative {
+            0i64.checked_sub_unsigned(magnitude)
+        } else {
+            i64::try_from(magnitude).ok()
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        let magnitude = self
+            .digits
+            .iter()
+            .rev()
+            .fold(0.0, |f, &d| f * 4294967296.0 + d as f64);
+        if self.negative {
+            -magnitude
+        } else {
+            magnitude
+        }
+    }
+
+    /// The quotient rounded towards zero, and the remainder, which has the
+    /// sign of `self`. Panics if `divisor` is zero.
+    pub fn div_rem(&self, divisor: &BigInt) -> (BigInt, BigInt) {
+        assert!(!divisor.is_zero(), "division by zero");
+        let (q, r) = div_rem_magnitude(&self.digits, &divisor.digits);
+        let quotient = BigInt::new(self.negative != divisor.negative, q);
+        let remainder = BigInt::new(self.negative, r);
+        (quotient, remainder)
+    }
+
+    /// The greatest common divisor, which is never negative.
+    pub fn gcd(&self, other: &BigInt) -> BigInt {
+        let (mut a, mut b) = (self.abs(), other.abs());
+        while !b.is_zero() {
+            let r = a.div_rem(&b).1;
+            a = b;
+            b = r;
+        }
+        a
+    }
+}
+
+impl From<i64> for BigInt {
+    fn from(n: i64) -> BigInt {
+        let magnitude = n.unsigned_abs();
+        BigInt::new(n < 0, vec![magnitude as u32, (magnitude >> 32) as u32])
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &BigInt) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_magnitude(&self.digits, &other.digits),
+            (true, true) => cmp_magnitude(&other.digits, &self.digits),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &BigInt) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Neg for &BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        BigInt::new(!self.negative, self.digits.clone())
+    }
+}
+
+impl Add for &BigInt {
+    type Output = BigInt;
+
+    fn add(self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::new(self.negative, add_magnitude(&self.digits, &other.digits));
+        }
+        // Opposite signs: subtract the smaller magnitude from the larger.
+        match cmp_magnitude(&self.digits, &other.digits) {
+            Ordering::Less => {
+                BigInt::new(other.negative, sub_magnitude(&other.digits, &self.digits))
+            }
+            _ => BigInt::new(self.negative, sub_magnitude(&self.digits, &other.digits)),
+        }
+    }
+}
+
+impl Sub for &BigInt {
+    type Output = BigInt;
+
+    fn sub(self, other: &BigInt) -> BigInt {
+        self + &-other
+    }
+}
+
+impl Mul for &BigInt {
+    type Output = BigInt;
+
+    fn mul(self, other: &BigInt) -> BigInt {
+        let mut product = vec![0u32; self.digits.len() + other.digits.len()];
+        for (i, &a) in self.digits.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, &b) in other.digits.iter().enumerate() {
+                let t = product[i + j] as u64 + a as u64 * b as u64 + carry;
+                product[i + j] = t as u32;
+                carry = t >> 32;
+            }
+            product[i + other.digits.len()] = carry as u32;
+        }
+        BigInt::new(self.negative != other.negative, product)
+    }
+}
+
+impl Display for BigInt {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+        let mut chunks = Vec::new();
+        let mut magnitude = self.digits.clone();
+        while !magnitude.is_empty() {
+            chunks.push(div_small(&mut magnitude, CHUNK));
+        }
+        if self.negative {
+            write!(f, "-")?;
+        }
+        write!(f, "{}", chunks.pop().unwrap())?;
+        for chunk in chunks.iter().rev() {
+            write!(f, "{:09}", chunk)?;
+        }
+        Ok(())
+    }
+}
+
+fn cmp_magnitude(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    let mut sum = Vec::with_capacity(long.len() + 1);
+    let mut carry = 0u64;
+    for (i, &d) in long.iter().enumerate() {
+        let t = d as u64 + *short.get(i).unwrap_or(&0) as u64 + carry;
+        sum.push(t as u32);
+        carry = t >> 32;
+    }
+    sum.push(carry as u32);
+    sum
+}
+
+/// `a - b`, where `a` is at least `b`.
+fn sub_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut difference = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+    for (i, &d) in a.iter().enumerate() {
+        let t = d as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        difference.push(t as u32);
+        borrow = (t < 0) as i64;
+    }
+    difference
+}
+
+/// `digits = digits * factor + addend`.
+fn mul_small_add(digits: &mut Vec<u32>, factor: u32, addend: u32) {
+    let mut carry = addend as u64;
+    for d in digits.iter_mut() {
+        let t = *d as u64 * factor as u64 + carry;
+        *d = t as u32;
+        carry = t >> 32;
+    }
+    if carry > 0 {
+        digits.push(carry as u32);
+    }
+}
+
+/// Divides `digits` by `divisor` in place and returns the remainder.
+fn div_small(digits: &mut Vec<u32>, divisor: u32) -> u32 {
+    let mut remainder = 0u64;
+    for d in digits.iter_mut().rev() {
+        let t = remainder << 32 | *d as u64;
+        *d = (t / divisor as u64) as u32;
+        remainder = t % divisor as u64;
+    }
+    while digits.last() == Some(&0) {
+        digits.pop();
+    }
+    remainder as u32
+}
+
+/// Long division of magnitudes, Knuth's algorithm D (TAOCP 4.3.1).
+fn div_rem_magnitude(u: &[u32], v: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if cmp_magnitude(u, v) == Ordering::Less {
+        return (Vec::new(), u.to_vec());
+    }
+    if v.len() == 1 {
+        let mut q = u.to_vec();
+        let r = div_small(&mut q, v[0]);
+        return (q, vec![r]);
+    }
+    // Normalize so that the top digit of the divisor has its high bit set,
+    // which keeps every estimate of a quotient digit at most 2 too large.
+    let shift = v.last().unwrap().leading_zeros();
+    let v = shift_left(v, shift);
+    let mut u = shift_left(u, shift);
+    u.push(0);
+    let n = v.len();
+    let mut q = vec![0u32; u.len() - n];
+    let base = 1u64 << 32;
+    for j in (0..q.len()).rev() {
+        let top = (u[j + n] as u64) << 32 | u[j + n - 1] as u64;
+        let mut qhat = top / v[n - 1] as u64;
+        let mut rhat = top % v[n - 1] as u64;
+        while qhat >= base || qhat * v[n - 2] as u64 > (rhat << 32 | u[j + n - 2] as u64) {
+            qhat -= 1;
+            rhat += v[n - 1] as u64;
+            if rhat >= base {
+                break;
+            }
+        }
+        // Subtract qhat * v from the current window of u.
+        let mut borrow = 0i64;
+        let mut carry = 0u64;
+        for i in 0..n {
+            let p = qhat * v[i] as u64 + carry;
+            carry = p >> 32;
+            let t = u[i + j] as i64 - borrow - (p & 0xffff_ffff) as i64;
+            u[i + j] = t as u32;
+            borrow = (t < 0) as i64;
+        }
+        let t = u[j + n] as i64 - borrow - carry as i64;
+        u[j + n] = t as u32;
+        q[j] = qhat as u32;
+        if t < 0 {
+            // qhat was one too large: add v back.
+            q[j] = q[j].wrapping_sub(1);
+            let mut carry = 0u64;
+            for i in 0..n {
+                let t = u[i + j] as u64 + v[i] as u64 + carry;
+                u[i + j] = t as u32;
+                carry = t >> 32;
+            }
+            u[j + n] = u[j + n].wrapping_add(carry as u32);
+        }
+    }
+    u.truncate(n);
+    (q, shift_right(&u, shift))
+}
+
+fn shift_left(digits: &[u32], shift: u32) -> Vec<u32> {
+    if shift == 0 {
+        return digits.to_vec();
+    }
+    let mut shifted = Vec::with_capacity(digits.len() + 1);
+    let mut carry = 0;
+    for &d in digits {
+        shifted.push(d << shift | carry);
+        carry = d >> (32 - shift);
+    }
+    if carry > 0 {
+        shifted.push(carry);
+    }
+    shifted
+}
+
+fn shift_right(digits: &[u32], shift: u32) -> Vec<u32> {
+    if shift == 0 {
+        return digits.to_vec();
+    }
+    let mut shifted = vec![0; digits.len()];
+    for i in 0..digits.len() {
+        let high = digits.get(i + 1).map_or(0, |&d| d << (32 - shift));
+        shifted[i] = digits[i] >> shift | high;
+    }
+    shifted
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Compares against `i128` arithmetic on pseudo-random operands of up
+    /// to 62 bits, so that products and quotients span several digits.
+    #[test]
+    fn test_arithmetic() {
+        let mut seed = 0x2545_f491_4f6c_dd1du64;
+        let mut next = || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            let bits = seed % 62 + 1;
+            ((seed >> 2) as i64 >> (62 - bits)) * if seed & 1 == 0 { 1 } else { -1 }
+        };
+        for _ in 0..2000 {
+            let (a, b) = (next(), next());
+            let (x, y) = (BigInt::from(a), BigInt::from(b));
+            let (a, b) = (a as i128, b as i128);
+            assert_eq!((&x + &y).to_string(), (a + b).to_string());
+            assert_eq!((&x - &y).to_string(), (a - b).to_string());
+            let product = &x * &y;
+            assert_eq!(product.to_string(), (a * b).to_string());
+            assert_eq!(x.cmp(&y), a.cmp(&b));
+            if b != 0 {
+                let (q, r) = product.div_rem(&y);
+                assert_eq!(
+                    (q.to_string(), r.to_string()),
+                    (((a * b) / b).to_string(), "0".into())
+                );
+                let (q, r) = (&product + &x).div_rem(&y);
+                let n = a * b + a;
+                assert_eq!(
+                    (q.to_string(), r.to_string()),
+                    ((n / b).to_string(), (n % b).to_string())
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_and_display() {
+        let cases = vec![
+            "0",
+            "-1",
+            "4294967296",
+            "-18446744073709551616",
+            "30414093201713378043612608166064768844377641568960512000000000000",
+        ];
+        for s in cases {
+            assert_eq!(BigInt::parse(s).unwrap().to_string(), s);
+        }
+        assert_eq!(BigInt::parse("+007").unwrap().to_string(), "7");
+        assert_eq!(BigInt::parse("-0").unwrap(), BigInt::from(0));
+        assert_eq!(BigInt::parse("1x"), None);
+        assert_eq!(BigInt::parse("-"), None);
+        assert_eq!(BigInt::from(i64::MIN).to_i64(), Some(i64::MIN));
+        assert_eq!(BigInt::parse("9223372036854775808").unwrap().to_i64(), None);
+        assert_eq!(BigInt::pow2(64).to_string(), "18446744073709551616");
+    }
+}
@@ -1,54 +1,619 @@
+use crate::evaluator::parse_command;
 use crate::evaluator::Evaluator;
 
-use super::tokenizer::tokenize;
-use super::parser::parse;
+use super::interpreter::quote;
+use super::interpreter::Interpreter;
+use super::value::Value;
 
-use crate::parsing_utils::tokenize_error_to_string;
-use crate::parsing_utils::parse_error_to_string;
+/// A Lisp REPL. Definitions persist between inputs.
+#[derive(Debug, Default)]
+pub struct Lisp {
+    interpreter: Interpreter,
+}
+
+impl Lisp {
+    fn run_command(&mut self, name: &str, arg: &str) -> String {
+        match name {
+            // Shows forms with all macro calls expanded, without evaluating them.
+            "expand" => match self.interpreter.expand_source(arg) {
+                Ok(forms) => {
+                    let heap = &mut self.interpreter.heap;
+                    let forms: Vec<Value> = forms.iter().map(|f| quote(heap, f)).collect();
+                    let forms: Vec<String> = forms.iter().map(|f| heap.show(f).to_string()).collect();
+                    forms.join("\n")
+                }
+                Err(e) => e,
+            },
+            "load" => match std::fs::read_to_string(arg) {
+                Ok(source) => self.eval(&source),
+                Err(e) => format!("Error: Cannot read {}: {}", arg, e),
+            },
+            _ => format!("Error: Unknown command ':{}'", name),
+        }
+    }
 
-#[derive(Debug)]
-pub struct Lisp;
+    fn eval(&mut self, source: &str) -> String {
+        let result = self.interpreter.eval_source(source);
+        // Text written by `print` comes first, even if evaluation failed.
+        let mut out = std::mem::take(&mut self.interpreter.output);
+        match result {
+            Ok(Value::Void) => {}
+            Ok(value) => out.push_str(&self.interpreter.heap.show(&value).to_string()),
+            Err(e) => out.push_str(&e),
+        }
+        out
+    }
+}
 
 impl Evaluator for Lisp {
-    fn run(&self, input: &str) -> String {
-        let mut out = String::new();
-        out.push_str("input: ");
-        out.push_str(input);
-        let tokens = tokenize(input).map_err(|e| tokenize_error_to_string(e, input));
-        if tokens.is_err() {
-            return tokens.unwrap_err();
+    fn run(&mut self, input: &str) -> String {
+        if let Some((name, arg)) = parse_command(input) {
+            return self.run_command(name, arg);
         }
-        let tokens = tokens.unwrap();
+        self.eval(input)
+    }
+
+    fn __debug__(&self) -> String {
+        format!("{:?}", self)
+    }
+
+    fn name(&self) -> String {
+        "Lisp".to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn run_all(lisp: &mut Lisp, inputs: &[&str]) -> Vec<String> {
+        inputs.iter().map(|input| lisp.run(input)).collect()
+    }
+
+    #[test]
+    fn test_eval() {
+        let cases = vec![
+            ("(+ 1 2)", "3"),
+            ("(- 10 4 3)", "3"),
+            ("(- 5)", "-5"),
+            ("(* 2 3 4)", "24"),
+            ("(/ 7 2)", "7/2"),
+            ("(< 1 2 3)", "#t"),
+            ("(> 1 2)", "#f"),
+            ("(= 2 2)", "#t"),
+            ("(if (< 1 2) 10 20)", "10"),
+            ("((lambda (x y) (* x y)) 6 7)", "42"),
+            ("(cons 1 (cons 2 (cons 3 (car (cons 4 5)))))", "(1 2 3 . 4)"),
+            ("(cdr (cons 1 (cons 2 (+ 0))))", "(2 . 0)"),
+            ("(print 1 2 (+ 1 2))", "1 2 3\n"),
+            ("'(1 2 3)", "(1 2 3)"),
+            ("(car '(a b))", "a"),
+            ("''a", "(quote a)"),
+            ("'()", "()"),
+            ("\"hello\"", "\"hello\""),
+            ("(print \"hello\" 'world)", "hello world\n"),
+            ("(cons #t (cons #f #n))", "(#t #f)"),
+            ("(if #f 1 2)", "2"),
+            ("(append '(1 2) '(3) '() 4)", "(1 2 3 . 4)"),
+            ("`(a ,(+ 1 2) ,@(cons 4 (cons 5 #n)) b)", "(a 3 4 5 b)"),
+            ("`(1 `(2 ,(3 ,(+ 1 3))))", "(1 (quasiquote (2 (unquote (3 4)))))"),
+            ("`'(,(+ 1 1))", "(quote (2))"),
+            ("(- 5 -3)", "8"),
+            ("'(-1 +2 - ... ->x)", "(-1 2 - ... ->x)"),
+            ("(list (null? '()) (list? '(1)) (number? 'a) (eq? 'a 'a))", "(#t #t #f #t)"),
+        ];
+
+        for (input, expected) in cases {
+            let mut lisp = Lisp::default();
+            assert_eq!(lisp.run(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_closures() {
+        let mut lisp = Lisp::default();
+        let out = run_all(
+            &mut lisp,
+            &[
+                "(define (make-adder n) (lambda (x) (+ x n)))",
+                "(define add2 (make-adder 2))",
+                "(add2 40)",
+                "(define (fact n) (if (< n 2) 1 (* n (fact (- n 1)))))",
+                "(fact 10)",
+                "add2",
+            ],
+        );
+        assert_eq!(out[2], "42");
+        assert_eq!(out[4], "3628800");
+        assert_eq!(out[5], "#<procedure>");
+    }
+
+    #[test]
+    fn test_macros() {
+        let mut lisp = Lisp::default();
+        let out = run_all(
+            &mut lisp,
+            &[
+                "(defmacro swap (a b) `(define ,a ,b))",
+                "(swap x (+ 1 2))",
+                "x",
+                "(define (count . xs) (if (null? xs) 0 (+ 1 (apply-count (cdr xs)))))",
+                "(define (apply-count xs) (if (null? xs) 0 (+ 1 (apply-count (cdr xs)))))",
+                "(count 1 2 3)",
+                "(define (tail x . rest) rest)",
+                "(tail 1 2 3)",
+                "(let ((a 1) (b 2)) (+ a b))",
+                "(cond ((< 2 1) 'a) ((< 1 2) 'b) (else 'c))",
+                "(cond (#f 1) (else 2 3))",
+                "(and 1 2 3)",
+                "(and 1 #f 3)",
+                "(or #f 2 (car 1))",
+                "(let ((t 5)) (or #f t))",
+                "(when (< 1 2) (print 1) 2)",
+                "(unless (< 1 2) 3)",
+                "(map (lambda (x) (* x x)) (list 1 2 3))",
+                ":expand (when a b)",
+                ":expand '(when a b)",
+                ":expand (lambda (let) (and let))",
+                "(when (< 1 2) (define w 4) (* w w))",
+                "(if (< 1 2) (begin (define w 4) (* w w)))",
+                "(cond ((< 2 1) 'a) (else (define c 5) (+ c 1)))",
+                ":expand (cond (a b) (else c))",
+            ],
+        );
+        assert_eq!(out[2], "3");
+        assert_eq!(out[5], "3");
+        assert_eq!(out[7], "(2 3)");
+        assert_eq!(out[8], "3");
+        assert_eq!(out[9], "b");
+        assert_eq!(out[10], "3");
+        assert_eq!(out[11], "3");
+        assert_eq!(out[12], "#f");
+        assert_eq!(out[13], "2");
+        assert_eq!(out[14], "5");
+        assert_eq!(out[15], "1\n2");
+        assert_eq!(out[16], "");
+        assert_eq!(out[17], "(1 4 9)");
+        assert_eq!(out[18], "(if a (begin b))");
+        assert_eq!(out[19], "(quote (when a b))");
+        assert_eq!(out[20], "(lambda (let) let)");
+        assert_eq!(out[21], "16");
+        assert_eq!(out[21], out[22]);
+        assert_eq!(out[23], "6");
+        assert_eq!(out[24], "(if a (begin b) (begin c))");
+    }
+
+    #[test]
+    fn test_redefined_names() {
+        let mut lisp = Lisp::default();
+        let out = run_all(
+            &mut lisp,
+            &[
+                "(define (map f items) 0)",
+                "(define (cons a b) 0)",
+                "(set! car cdr)",
+                "(let ((x 1) (y 2)) (+ x y))",
+                "(letrec ((f (lambda (n) (if (= n 0) 'done (f (- n 1)))))) (f 3))",
+                "(cond ((< 2 1) 'a) (else 'b))",
+                "`(a ,(+ 1 2) ,@(list 4 5))",
+                "(list (map 1 2) (cons 1 2) (car '(1 2)))",
+            ],
+        );
+        assert_eq!(out[3], "3");
+        assert_eq!(out[4], "done");
+        assert_eq!(out[5], "b");
+        assert_eq!(out[6], "(a 3 4 5)");
+        assert_eq!(out[7], "(0 0 (2))");
+    }
+
+    #[test]
+    fn test_tail_calls() {
+        let mut lisp = Lisp::default();
+        let out = run_all(
+            &mut lisp,
+            &[
+                "(define (loop n) (if (= n 0) 'done (loop (- n 1))))",
+                "(loop 1000000)",
+                "(define (even n) (cond ((= n 0) #t) (else (odd (- n 1)))))",
+                "(define (odd n) (when #t (begin (if (= n 0) #f (even (- n 1))))))",
+                "(even 10001)",
+                "(define (sum n acc) (let ((m (- n 1))) (if (< n 1) acc (sum m (+ acc n)))))",
+                "(sum 10000 0)",
+                "(begin (print 1) (print 2) 3)",
+                "(begin)",
+            ],
+        );
+        assert_eq!(out[1], "done");
+        assert_eq!(out[4], "#f");
+        assert_eq!(out[6], "50005000");
+        assert_eq!(out[7], "1\n2\n3");
+        assert_eq!(out[8], "");
+    }
+
+    #[test]
+    fn test_numbers() {
+        let cases = vec![
+            ("(* 99999999999 99999999999)", "9999999999800000000001"),
+            ("(- -9223372036854775808 1)", "-9223372036854775809"),
+            ("(- (+ 9223372036854775807 1) 1)", "9223372036854775807"),
+            ("(/ 1 3)", "1/3"),
+            ("(+ 1/2 1/3)", "5/6"),
+            ("(/ 6 3)", "2"),
+            ("(/ 4)", "1/4"),
+            ("(* 2/3 3/2)", "1"),
+            ("-6/4", "-3/2"),
+            ("(+ 1 2.5)", "3.5"),
+            ("(* 1/2 0.5)", "0.25"),
+            ("(/ 1 0.0)", "+inf.0"),
+            ("(exact 0.5)", "1/2"),
+            ("(inexact 1/4)", "0.25"),
+            ("(quotient 7 2)", "3"),
+            ("(remainder -7 2)", "-1"),
+            ("(quotient 100000000000000000000 3)", "33333333333333333333"),
+            ("(= 1 1.0)", "#t"),
+            ("(eq? 1 1.0)", "#f"),
+            ("(<= 1/3 0.5 1 1)", "#t"),
+            ("(>= 2 3)", "#f"),
+            ("(< 1 +nan.0)", "#f"),
+            ("(list (integer? 2.0) (integer? 1/2) (exact? 1/2) (exact? 1e3))", "(#t #f #t #f)"),
+        ];
 
-        out.push_str("\ntokens: [");
-        for token in tokens.iter() {
-            out.push_str(&format!("\n\t{}, ", token));
+        for (input, expected) in cases {
+            let mut lisp = Lisp::default();
+            assert_eq!(lisp.run(input), expected, "{}", input);
         }
-        out.push_str("\n]\n");
 
-        let expr = parse(&tokens);
-        if expr.is_err() {
-            let err = parse_error_to_string(expr.err().unwrap(), input);
-            out.push_str(err.as_str());
-            return out;
+        let mut lisp = Lisp::default();
+        let out = run_all(
+            &mut lisp,
+            &["(define (fact n) (if (= n 0) 1 (* n (fact (- n 1)))))", "(fact 30)"],
+        );
+        assert_eq!(out[1], "265252859812191058636308480000000");
+    }
+
+    #[test]
+    fn test_mutation() {
+        let mut lisp = Lisp::default();
+        let out = run_all(
+            &mut lisp,
+            &[
+                "(define x 1)",
+                "(set! x (+ x 1))",
+                "x",
+                "(define (make-counter) (let ((n 0)) (lambda () (set! n (+ n 1)) n)))",
+                "(define c (make-counter))",
+                "(begin (c) (c) (list (c) ((make-counter))))",
+                "(define p (list 1 2 3))",
+                "(begin (set-car! p 'a) (set-cdr! (cdr p) '(c)) p)",
+                "(begin (set-cdr! (cdr (cdr p)) p) (list p (list? p)))",
+                "(let ((x 3) (y x)) (list x y))",
+                "(let* ((x 3) (y x)) (list x y))",
+                "(letrec ((ev? (lambda (n) (if (= n 0) #t (od? (- n 1))))) (od? (lambda (n) (if (= n 0) #f (ev? (- n 1)))))) (ev? 10))",
+                "(let () (define y 5) (set! y (* y 2)) y)",
+                "(let ((x 10)) (set! x 20) x)",
+                "x",
+            ],
+        );
+        assert_eq!(out[1], "");
+        assert_eq!(out[2], "2");
+        assert_eq!(out[5], "(3 1)");
+        assert_eq!(out[7], "(a 2 c)");
+        assert_eq!(out[8], "((a 2 c ...) #f)");
+        assert_eq!(out[9], "(3 2)");
+        assert_eq!(out[10], "(3 3)");
+        assert_eq!(out[11], "#t");
+        assert_eq!(out[12], "10");
+        assert_eq!(out[13], "20");
+        assert_eq!(out[14], "2");
+    }
+
+    #[test]
+    fn test_circular_structure() {
+        let mut lisp = Lisp::default();
+        let out = run_all(
+            &mut lisp,
+            &[
+                "(define p (list 1 2))",
+                "(set-car! p p)",
+                "p",
+                "(begin (set-cdr! (cdr p) p) p)",
+                "(let ((a (list 1))) (list a a))",
+                "(defmacro knot () (let ((p (list '+ 1))) (set-car! (cdr p) p) p))",
+                "(knot)",
+                "(defmacro spin () (let ((p (list 'begin))) (set-cdr! p p) p))",
+                "(spin)",
+                "(define v (make-vector 2 0))",
+                "(begin (vector-set! v 0 v) (vector-set! v 1 (list v)) v)",
+            ],
+        );
+        assert_eq!(out[2], "(... 2)");
+        assert_eq!(out[3], "(... 2 ...)");
+        assert_eq!(out[4], "((1) (1))");
+        assert!(out[6].starts_with("Error: Macro expansion contains a circular list, which is not code\n"), "{}", out[6]);
+        assert!(out[8].starts_with("Error: Macro expansion contains a circular list, which is not code\n"), "{}", out[8]);
+        assert_eq!(out[10], "#(... (...))");
+    }
+
+    #[test]
+    fn test_garbage_collection() {
+        let mut lisp = Lisp::default();
+        let out = run_all(
+            &mut lisp,
+            &[
+                "(define (churn n) (if (> n 0) (begin (letrec ((f (lambda () g)) (g (lambda () f))) f) (let ((p (list 1 2 3))) (set-cdr! (cdr (cdr p)) p)) (let ((t (make-hash-table))) (hash-table-set! t 'self t)) (churn (- n 1)))))",
+                "(define (live) (cdr (car (gc-stats))))",
+                "(define (collections) (cdr (car (cdr (gc-stats)))))",
+                "(define kept (list 1 2 3))",
+                "(churn 20000)",
+                "(define before (live))",
+                "(churn 20000)",
+                "(list (= before (live)) (> (collections) 0) kept)",
+            ],
+        );
+        assert_eq!(out[7], "(#t #t (1 2 3))");
+    }
+
+    #[test]
+    fn test_strings() {
+        let cases = vec![
+            ("(string-append \"foo\" \"\" \"bar\")", "\"foobar\""),
+            ("(string-length \"héllo\")", "5"),
+            ("(substring \"hello world\" 6)", "\"world\""),
+            ("(substring \"hello\" 1 3)", "\"el\""),
+            ("(string-ref \"abc\" 2)", "#\\c"),
+            ("(string->symbol \"abc\")", "abc"),
+            ("(symbol->string 'abc)", "\"abc\""),
+            ("(number->string (/ 1 2))", "\"1/2\""),
+            ("(string->number \"-2.5\")", "-2.5"),
+            ("(string->number \"abc\")", "#f"),
+            ("(string->list \"ab\")", "(#\\a #\\b)"),
+            ("(list->string (list #\\a #\\space #\\())", "\"a (\""),
+            ("(list #\\a #\\space #\\newline #\\))", "(#\\a #\\space #\\newline #\\))"),
+            ("(char->integer #\\A)", "65"),
+            ("(integer->char 955)", "#\\λ"),
+            ("(string=? \"a\" \"a\" \"b\")", "#f"),
+            ("(list (string? \"a\") (char? #\\a) (symbol? 'a) (symbol? \"a\"))", "(#t #t #t #f)"),
+            ("(eq? #\\a #\\a)", "#t"),
+            ("\"say \\\"hi\\\"\\n\"", "\"say \\\"hi\\\"\\n\""),
+            ("(print \"a\\tb\" #\\c)", "a\tb c\n"),
+        ];
+
+        for (input, expected) in cases {
+            let mut lisp = Lisp::default();
+            assert_eq!(lisp.run(input), expected, "{}", input);
         }
+    }
 
-        let expr = expr.unwrap();
+    #[test]
+    fn test_vectors_and_hash_tables() {
+        let mut lisp = Lisp::default();
+        let out = run_all(
+            &mut lisp,
+            &[
+                "(define v (make-vector 3 0))",
+                "(vector-set! v 1 'x)",
+                "v",
+                "(list (vector-ref v 1) (vector-length v) (vector? v) (vector? '(1)))",
+                "(vector->list (vector 1 \"a\" #\\b))",
+                "(list->vector '(1 2))",
+                "(define h (make-hash-table))",
+                "(hash-table-set! h 'a 1)",
+                "(hash-table-set! h \"b\" 2)",
+                "(hash-table-set! h 'a 3)",
+                "(list (hash-table-ref h 'a) (hash-table-ref h \"b\") (hash-table-ref h 'c 0))",
+                "(list (hash-table-count h) (hash-table-contains? h 'a) (hash-table-contains? h 1))",
+                "(hash-table-delete! h 'a)",
+                "(list (hash-table-keys h) (hash-table? h) h)",
+                "(define (count words table) (if (null? words) table (begin (hash-table-set! table (car words) (+ 1 (hash-table-ref table (car words) 0))) (count (cdr words) table))))",
+                "(hash-table-ref (count '(a b a c a) (make-hash-table)) 'a)",
+            ],
+        );
+        assert_eq!(out[2], "#(0 x 0)");
+        assert_eq!(out[3], "(x 3 #t #f)");
+        assert_eq!(out[4], "(1 \"a\" #\\b)");
+        assert_eq!(out[5], "#(1 2)");
+        assert_eq!(out[10], "(3 2 0)");
+        assert_eq!(out[11], "(2 #t #f)");
+        assert_eq!(out[13], "((\"b\") #t #<hash-table 1>)");
+        assert_eq!(out[15], "3");
+    }
 
-        out.push_str("\nparsed: ");
-        out.push_str(&format!("{:#?}", expr));
+    #[test]
+    fn test_continuations() {
+        let mut lisp = Lisp::default();
+        let out = run_all(
+            &mut lisp,
+            &[
+                "(call/cc (lambda (k) (+ 1 (k 42))))",
+                "(define (find-negative items)
+                   (call/cc (lambda (return)
+                     (map (lambda (x) (if (< x 0) (return x) x)) items)
+                     #f)))",
+                "(find-negative '(1 -2 3))",
+                "(find-negative (list 1 2 3))",
+                "(define p (cons 1 (call-with-current-continuation (lambda (k) k))))",
+                "((cdr p) 5)",
+                "p",
+                "(call/cc call/cc)",
+                "(call/cc 1 2)",
+            ],
+        );
+        assert_eq!(out[0], "42");
+        assert_eq!(out[2], "-2");
+        assert_eq!(out[3], "#f");
+        assert_eq!(out[5], "p");
+        assert_eq!(out[6], "(1 . 5)");
+        assert_eq!(out[7], "#<continuation>");
+        assert!(out[8].starts_with("Error: Expected 1 argument, got 2\n"));
+    }
 
+    #[test]
+    fn test_generators() {
+        let mut lisp = Lisp::default();
+        let out = run_all(
+            &mut lisp,
+            &[
+                // A generator is the next item, paired with the continuation
+                // that resumes the walk with a new place to return to.
+                "(define (walk items return)
+                   (if (null? items)
+                     (return '())
+                     (walk (cdr items)
+                           (call/cc (lambda (resume)
+                             (return (cons (car items) resume)))))))",
+                "(define (start items) (call/cc (lambda (return) (walk items return))))",
+                "(define (next gen) (call/cc (lambda (return) ((cdr gen) return))))",
+                "(define (collect gen) (if (null? gen) '() (cons (car gen) (collect (next gen)))))",
+                "(collect (start '(a b c)))",
+                "(define (zip g h)
+                   (if (null? g) '() (cons (list (car g) (car h)) (zip (next g) (next h)))))",
+                "(zip (start '(1 2 3)) (start '(a b c)))",
+            ],
+        );
+        assert_eq!(out[4], "(a b c)");
+        assert_eq!(out[6], "((1 a) (2 b) (3 c))");
+    }
 
-        return out;
+    #[test]
+    fn test_dynamic_wind() {
+        let mut lisp = Lisp::default();
+        let out = run_all(
+            &mut lisp,
+            &[
+                "(dynamic-wind (lambda () (print 1)) (lambda () 2) (lambda () (print 3)))",
+                "(call/cc (lambda (k)
+                   (dynamic-wind
+                     (lambda () (print 'in))
+                     (lambda () (k 'escaped) (print 'unreachable))
+                     (lambda () (print 'out)))))",
+                "(dynamic-wind
+                   (lambda () (print 'a))
+                   (lambda ()
+                     (call/cc (lambda (k)
+                       (dynamic-wind (lambda () (print 'b)) (lambda () (k 'x)) (lambda () (print 'c))))))
+                   (lambda () (print 'd)))",
+                "(define w
+                   (dynamic-wind
+                     (lambda () (print 'before))
+                     (lambda () (call/cc (lambda (k) k)))
+                     (lambda () (print 'after))))",
+                "(w 5)",
+                "w",
+            ],
+        );
+        assert_eq!(out[0], "1\n3\n2");
+        assert_eq!(out[1], "in\nout\nescaped");
+        assert_eq!(out[2], "a\nb\nc\nd\nx");
+        assert_eq!(out[3], "before\nafter\nw");
+        assert_eq!(out[4], "before\nafter\nw");
+        assert_eq!(out[5], "5");
     }
 
-    fn __debug__(&self) -> String {
-        format!("{:?}", self)
+    #[test]
+    fn test_programs() {
+        let dir = std::env::temp_dir().join(format!("lisp-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let lib = dir.join("lib.scm");
+        std::fs::write(&lib, "; A library\n(define (square x) (* x x))\n\n(square 3) ; nine\n").unwrap();
+        let broken = dir.join("broken.scm");
+        std::fs::write(&broken, "(define y 1)\n(define z (car y))\n").unwrap();
+        let (lib, broken) = (lib.to_str().unwrap(), broken.to_str().unwrap());
+
+        let mut lisp = Lisp::default();
+        let load = format!("(load \"{}\")", lib);
+        let out = run_all(
+            &mut lisp,
+            &[
+                "(define x 2) (+ x 1)",
+                "; only a comment",
+                "(print 1) ; one\n(print 2)",
+                ":expand (when a b) 'c",
+                &load,
+                "(square 4)",
+                &format!(":load {}", lib),
+                &format!(":load {}", broken),
+                "y",
+            ],
+        );
+        assert_eq!(out[0], "3");
+        assert_eq!(out[1], "");
+        assert_eq!(out[2], "1\n2\n");
+        assert_eq!(out[3], "(if a (begin b))\n(quote c)");
+        assert_eq!(out[4], "9");
+        assert_eq!(out[5], "16");
+        assert_eq!(out[6], "9");
+        assert!(out[7].starts_with("Error: Expected a pair, got number 1\nline 2:\n"), "{}", out[7]);
+        assert_eq!(out[8], "1");
+
+        let out = lisp.run(&format!("(load \"{}\")", broken));
+        assert!(out.starts_with(&format!("Error: In {}:\nError: Expected a pair", broken)), "{}", out);
+        let out = lisp.run("(load \"/nonexistent.scm\")");
+        assert!(out.starts_with("Error: Cannot read /nonexistent.scm: "), "{}", out);
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 
-    fn name(&self) -> String {
-        "Lisp".to_string()
+    #[test]
+    fn test_errors() {
+        let cases = vec![
+            ("(+ 1 car)", "Expected a number, got procedure #<primitive car>", "car"),
+            ("(car 1)", "Expected a pair, got number 1", "1"),
+            ("(foo 1)", "Unbound variable 'foo'", "foo"),
+            ("(/ 1 0)", "Division by zero", "0"),
+            ("(1 2)", "Expected a procedure, got number 1", "1"),
+            ("((lambda (x) x))", "Expected 1 argument, got 0", "((lambda (x) x))"),
+            ("()", "Cannot evaluate the empty list", "()"),
+            ("`,@'(1)", "',@' is only allowed inside a list", ",@'(1)"),
+            ("(+ 1 ,x)", "Unquote outside of a quasiquote", ",x"),
+            ("(append 1 '())", "Expected a list, got number 1", "1"),
+            ("(+ 1 1.2.3)", "Invalid number: 1.2.3", "1.2.3"),
+            ("((lambda (x . y) x))", "Expected at least 1 argument, got 0", "((lambda (x . y) x))"),
+            ("(1 . 2)", "Cannot evaluate a dotted list", "(1 . 2)"),
+            ("(/ 1/2 0)", "Division by zero", "0"),
+            ("(quotient 1 1/2)", "Expected an integer, got number 1/2", "1/2"),
+            ("(remainder 1.0 0)", "Division by zero", "0"),
+            ("(exact +inf.0)", "+inf.0 has no exact equivalent", "+inf.0"),
+            ("(set! y 1)", "Unbound variable 'y'", "y"),
+            ("(set! (car x) 1)", "Expected a variable", "(car x)"),
+            ("(set! x)", "'set!' expects 2 arguments, got 1", "(set! x)"),
+            ("(set-cdr! '() 1)", "Expected a pair, got nil ()", "'()"),
+            ("(string-append \"a\" 'b)", "Expected a string, got symbol b", "'b"),
+            ("(substring \"abc\" 1 5)", "Index 5 is out of range for length 3", "5"),
+            ("(substring \"abc\" 2 1)", "The end 1 is before the start 2", "1"),
+            ("(vector-ref (vector 1 2) 2)", "Index 2 is out of range for length 2", "2"),
+            ("(vector-ref (vector 1) -1)", "Expected an index, got number -1", "-1"),
+            ("(vector-set! '(1) 0 0)", "Expected a vector, got pair (1)", "'(1)"),
+            ("(make-vector 1000000000000000000)", "Cannot allocate a vector of length 1000000000000000000", "1000000000000000000"),
+            ("(hash-table-ref (make-hash-table) 'k)", "No value for the key k", "'k"),
+            ("(hash-table-set! (make-hash-table) car 1)", "Expected a hash table key, got procedure #<primitive car>", "car"),
+            ("(char->integer \"a\")", "Expected a character, got string \"a\"", "\"a\""),
+            ("(let ((x)) x)", "In the expansion of 'let': Expected a pair, got nil ()", "(let ((x)) x)"),
+        ];
+
+        for (input, message, offending) in cases {
+            let out = Lisp::default().run(input);
+            assert!(out.starts_with(&format!("Error: {}\n", message)), "{}", out);
+            let column = input[..input.rfind(offending).unwrap()].chars().count();
+            let marker = format!("\n{}{}{}", " ".repeat(column), crate::colors::RED, "^".repeat(offending.len()));
+            assert!(out.contains(&marker), "{}", out);
+        }
     }
-}
 
+    #[test]
+    fn test_errors_in_other_inputs() {
+        let cases = vec![
+            ("(g 5)", "In 'g': Expected a pair, got number 5", "(g 5)"),
+            ("(list 1 (g 5))", "In 'g': Expected a pair, got number 5", "(g 5)"),
+            ("(map car (list 1 2))", "In 'map': Expected a pair, got number 1", "(map car (list 1 2))"),
+        ];
 
+        for (input, message, offending) in cases {
+            let mut lisp = Lisp::default();
+            lisp.run("(define (g x) (car x))");
+            let out = lisp.run(input);
+            assert!(out.starts_with(&format!("Error: {}\n", message)), "{}", out);
+            let column = input[..input.rfind(offending).unwrap()].chars().count();
+            let marker = format!("\n{}{}{}", " ".repeat(column), crate::colors::RED, "^".repeat(offending.len()));
+            assert!(out.contains(&marker), "{}", out);
+        }
+    }
+}
@@ -0,0 +1,339 @@
+use super::value::Closure;
+use super::value::Env;
+use super::value::EnvRef;
+use super::value::HashTable;
+use super::value::Value;
+
+use crate::parsing_utils::Spanned;
+
+use std::collections::HashSet;
+use std::fmt::{self, Debug, Formatter};
+use std::rc::Rc;
+
+/// A reference to an object on the heap. It stays valid as long as the
+/// object is reachable from the roots of the collector.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(super) struct Handle(usize);
+
+/// The values that can refer to other values, and so take part in cycles.
+pub(super) enum Object {
+    Pair(Value, Value),
+    Vector(Vec<Value>),
+    HashTable(HashTable),
+    Closure(Closure),
+    Env(Env),
+}
+
+/// The fewest allocations between two collections. Past that, the heap may
+/// grow to twice the objects that survived the last collection.
+const MIN_ALLOCATIONS: usize = 10_000;
+
+/// The objects of a Lisp session, freed by a mark-sweep collector.
+#[derive(Default)]
+pub(super) struct Heap {
+    objects: Vec<Option<Object>>,
+    /// The slots of freed objects, reused by the next allocations.
+    free: Vec<usize>,
+    /// The allocations since the last collection.
+    allocations: usize,
+    /// The objects that survived the last collection.
+    survivors: usize,
+    pub collections: usize,
+    /// The objects freed by all collections so far.
+    pub freed: usize,
+}
+
+/// Finds the objects reachable from the roots given to it.
+#[derive(Default)]
+pub(super) struct Marker {
+    pending: Vec<Handle>,
+    /// The shared values outside the heap already traced, such as
+    /// continuations, which may share most of their stack.
+    visited: HashSet<*const ()>,
+}
+
+/// Values that may hold handles.
+pub(super) trait Trace {
+    fn trace(&self, marker: &mut Marker);
+}
+
+impl Heap {
+    pub fn alloc(&mut self, object: Object) -> Handle {
+        self.allocations += 1;
+        match self.free.pop() {
+            Some(i) => {
+                self.objects[i] = Some(object);
+                Handle(i)
+            }
+            None => {
+                self.objects.push(Some(object));
+                Handle(self.objects.len() - 1)
+            }
+        }
+    }
+
+    /// The number of objects allocated and not freed yet.
+    pub fn len(&self) -> usize {
+        self.objects.len() - self.free.len()
+    }
+
+    /// Whether enough was allocated since the last collection to collect
+    /// again.
+    pub fn is_due(&self) -> bool {
+        self.allocations >= self.survivors.max(MIN_ALLOCATIONS)
+    }
+
+    /// Frees every object that the roots in `marker` do not reach.
+    pub fn collect(&mut self, mut marker: Marker) {
+        let mut marked = vec![false; self.objects.len()];
+        while let Some(Handle(i)) = marker.pending.pop() {
+            if !marked[i] {
+                marked[i] = true;
+                self.objects[i]
+                    .as_ref()
+                    .expect("dangling handle")
+                    .trace(&mut marker);
+            }
+        }
+        for (i, slot) in self.objects.iter_mut().enumerate() {
+            if slot.is_some() && !marked[i] {
+                *slot = None;
+                self.free.push(i);
+                self.freed += 1;
+            }
+        }
+        self.survivors = self.len();
+        self.allocations = 0;
+        self.collections += 1;
+    }
+
+    fn get(&self, handle: Handle) -> &Object {
+        self.objects[handle.0].as_ref().expect("dangling handle")
+    }
+
+    fn get_mut(&mut self, handle: Handle) -> &mut Object {
+        self.objects[handle.0].as_mut().expect("dangling handle")
+    }
+
+    // Pairs and lists
+
+    pub fn cons(&mut self, car: Value, cdr: Value) -> Value {
+        Value::Pair(self.alloc(Object::Pair(car, cdr)))
+    }
+
+    pub fn list(&mut self, items: Vec<Value>) -> Value {
+        items
+            .into_iter()
+            .rev()
+            .fold(Value::Nil, |tail, v| self.cons(v, tail))
+    }
+
+    pub fn pair(&self, handle: Handle) -> (&Value, &Value) {
+        match self.get(handle) {
+            Object::Pair(car, cdr) => (car, cdr),
+            _ => unreachable!("not a pair"),
+        }
+    }
+
+    pub fn car(&self, handle: Handle) -> Value {
+        self.pair(handle).0.clone()
+    }
+
+    pub fn cdr(&self, handle: Handle) -> Value {
+        self.pair(handle).1.clone()
+    }
+
+    pub fn set_car(&mut self, handle: Handle, value: Value) {
+        match self.get_mut(handle) {
+            Object::Pair(car, _) => *car = value,
+            _ => unreachable!("not a pair"),
+        }
+    }
+
+    pub fn set_cdr(&mut self, handle: Handle, value: Value) {
+        match self.get_mut(handle) {
+            Object::Pair(_, cdr) => *cdr = value,
+            _ => unreachable!("not a pair"),
+        }
+    }
+
+    /// The elements of a proper list, or `None` if the value is not one.
+    /// A circular list is not: `slow` follows the list at half speed, and
+    /// catches up with `v` only if it runs in a circle.
+    pub fn to_vec(&self, value: &Value) -> Option<Vec<Value>> {
+        let mut items = Vec::new();
+        let mut v = value;
+        let mut slow = value;
+        loop {
+            match v {
+                Value::Nil => return Some(items),
+                Value::Pair(handle) => {
+                    let (car, cdr) = self.pair(*handle);
+                    items.push(car.clone());
+                    v = cdr;
+                }
+                _ => return None,
+            }
+            if items.len() % 2 == 0 {
+                if let Value::Pair(handle) = slow {
+                    slow = self.pair(*handle).1;
+                }
+                if let (Value::Pair(a), Value::Pair(b)) = (v, slow) {
+                    if a == b {
+                        return None;
+                    }
+                }
+            }
+        }
+    }
+
+    // Vectors and hash tables
+
+    pub fn vector(&self, handle: Handle) -> &Vec<Value> {
+        match self.get(handle) {
+            Object::Vector(items) => items,
+            _ => unreachable!("not a vector"),
+        }
+    }
+
+    pub fn vector_mut(&mut self, handle: Handle) -> &mut Vec<Value> {
+        match self.get_mut(handle) {
+            Object::Vector(items) => items,
+            _ => unreachable!("not a vector"),
+        }
+    }
+
+    pub fn hash_table(&self, handle: Handle) -> &HashTable {
+        match self.get(handle) {
+            Object::HashTable(table) => table,
+            _ => unreachable!("not a hash table"),
+        }
+    }
+
+    pub fn hash_table_mut(&mut self, handle: Handle) -> &mut HashTable {
+        match self.get_mut(handle) {
+            Object::HashTable(table) => table,
+            _ => unreachable!("not a hash table"),
+        }
+    }
+
+    // Closures and environments
+
+    pub fn closure(&self, handle: Handle) -> &Closure {
+        match self.get(handle) {
+            Object::Closure(closure) => closure,
+            _ => unreachable!("not a closure"),
+        }
+    }
+
+    pub fn env(&self, env: EnvRef) -> &Env {
+        match self.get(env) {
+            Object::Env(env) => env,
+            _ => unreachable!("not an environment"),
+        }
+    }
+
+    pub fn env_mut(&mut self, env: EnvRef) -> &mut Env {
+        match self.get_mut(env) {
+            Object::Env(env) => env,
+            _ => unreachable!("not an environment"),
+        }
+    }
+
+    pub fn new_env(&mut self, parent: Option<EnvRef>) -> EnvRef {
+        self.alloc(Object::Env(Env::new(parent)))
+    }
+
+    pub fn define(&mut self, env: EnvRef, name: &str, value: Value) {
+        self.env_mut(env).define(name, value);
+    }
+
+    /// The value of the innermost binding of `name`.
+    pub fn lookup(&self, env: EnvRef, name: &str) -> Option<Value> {
+        let mut scope = Some(env);
+        while let Some(env) = scope {
+            let env = self.env(env);
+            if let Some(value) = env.get(name) {
+                return Some(value.clone());
+            }
+            scope = env.parent;
+        }
+        None
+    }
+
+    /// Changes the innermost binding of `name`. Returns false if there is
+    /// none.
+    pub fn assign(&mut self, env: EnvRef, name: &str, value: Value) -> bool {
+        let mut scope = Some(env);
+        while let Some(env) = scope {
+            if let Some(v) = self.env_mut(env).get_mut(name) {
+                *v = value;
+                return true;
+            }
+            scope = self.env(env).parent;
+        }
+        false
+    }
+}
+
+impl Debug for Heap {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("Heap")
+            .field("objects", &self.len())
+            .field("collections", &self.collections)
+            .finish()
+    }
+}
+
+impl Marker {
+    pub fn mark(&mut self, handle: Handle) {
+        self.pending.push(handle);
+    }
+
+    /// Whether `rc` is traced for the first time in this collection.
+    pub fn first_visit<T>(&mut self, rc: &Rc<T>) -> bool {
+        self.visited.insert(Rc::as_ptr(rc) as *const ())
+    }
+}
+
+impl Trace for Object {
+    fn trace(&self, marker: &mut Marker) {
+        match self {
+            Object::Pair(car, cdr) => {
+                car.trace(marker);
+                cdr.trace(marker);
+            }
+            Object::Vector(items) => items.trace(marker),
+            Object::HashTable(table) => {
+                for (key, value) in table.entries.values() {
+                    key.trace(marker);
+                    value.trace(marker);
+                }
+            }
+            Object::Closure(closure) => marker.mark(closure.env),
+            Object::Env(env) => env.trace(marker),
+        }
+    }
+}
+
+impl<T: Trace> Trace for Vec<T> {
+    fn trace(&self, marker: &mut Marker) {
+        for item in self {
+            item.trace(marker);
+        }
+    }
+}
+
+impl<T: Trace> Trace for Option<T> {
+    fn trace(&self, marker: &mut Marker) {
+        if let Some(item) = self {
+            item.trace(marker);
+        }
+    }
+}
+
+impl<T: Trace> Trace for Spanned<T> {
+    fn trace(&self, marker: &mut Marker) {
+        self.item.trace(marker);
+    }
+}
@@ -0,0 +1,938 @@
+use super::ast::Expr;
+use super::ast::SExpr;
+use super::heap::Handle;
+use super::heap::Heap;
+use super::heap::Marker;
+use super::heap::Object;
+use super::heap::Trace;
+use super::parser::parse_program;
+use super::prelude;
+use super::primitives;
+use super::tokenizer::tokenize;
+use super::value::Closure;
+use super::value::Control;
+use super::value::Env;
+use super::value::EnvRef;
+use super::value::Params;
+use super::value::Value;
+
+use crate::parsing_utils::parse_error_to_string;
+use crate::parsing_utils::runtime_err;
+use crate::parsing_utils::runtime_err_within;
+use crate::parsing_utils::runtime_error_to_string;
+use crate::parsing_utils::tokenize_error_to_string;
+use crate::parsing_utils::RuntimeError;
+use crate::parsing_utils::Span;
+use crate::parsing_utils::Spanned;
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// The state of a Lisp session: the heap, the global environment, the
+/// macros defined so far and the text written by `print` since it was last
+/// taken.
+#[derive(Debug)]
+pub(super) struct Interpreter {
+    pub heap: Heap,
+    pub global: EnvRef,
+    /// The primitives and the prelude. Programs run in a copy of it, so
+    /// that redefining a name does not change what the prelude's macros and
+    /// procedures call.
+    core: EnvRef,
+    /// Macro transformers by name, as closures. They get the unevaluated
+    /// argument forms and return the form to evaluate instead.
+    pub macros: HashMap<String, Handle>,
+    pub output: String,
+    /// The number of symbols made by `gensym` so far.
+    pub gensyms: usize,
+    /// The `dynamic-wind` calls control is inside, innermost first.
+    winds: Option<Rc<Wind>>,
+    /// The `dynamic-wind` calls each evaluation in progress started inside,
+    /// restored when it ends.
+    outer_winds: Vec<Option<Rc<Wind>>>,
+    /// The stacks of the evaluations waiting for a primitive to return, and
+    /// the arguments of the primitive. A primitive such as `load` may
+    /// evaluate code itself, and collect garbage meanwhile.
+    suspended: Vec<(Vec<Frame>, Vec<Spanned<Value>>)>,
+    /// The input being evaluated.
+    source: Source,
+    /// The characters of all inputs read so far.
+    read: usize,
+}
+
+/// Where an input is among the spans of all inputs read. Each input is read
+/// at a different offset, so the span of a form also tells which input the
+/// form comes from.
+#[derive(Debug, Clone, Copy, Default)]
+struct Source {
+    start: usize,
+    length: usize,
+}
+
+impl Source {
+    fn contains(&self, span: Span) -> bool {
+        span.is_within(self.start, self.length)
+    }
+}
+
+/// A continuation captured by `call/cc`: the stack of the evaluator at the
+/// time, and the `dynamic-wind` calls it was inside.
+#[derive(Debug)]
+pub(super) struct Continuation {
+    frames: Vec<Frame>,
+    winds: Option<Rc<Wind>>,
+}
+
+/// The `before` and `after` thunks of a `dynamic-wind` call, and the calls
+/// it is nested in.
+#[derive(Debug)]
+struct Wind {
+    before: Spanned<Value>,
+    after: Spanned<Value>,
+    depth: usize,
+    parent: Option<Rc<Wind>>,
+}
+
+/// What the evaluator does next.
+enum State {
+    /// Evaluate `forms[index]` in the environment.
+    Eval(Rc<[SExpr]>, usize, EnvRef),
+    /// Apply a procedure to evaluated arguments. The span is the span of
+    /// the whole call.
+    Apply(Spanned<Value>, Vec<Spanned<Value>>, Span),
+    /// Pass a value to the frame on top of the stack.
+    Return(Value),
+}
+
+/// Work waiting for the value of a subexpression. Expressions in tail
+/// position push no frame, so loops written as tail recursion run in
+/// constant space.
+#[derive(Clone, Debug)]
+enum Frame {
+    /// Choose a branch of `(if test consequent alternative?)`.
+    If(Rc<[SExpr]>, EnvRef),
+    /// Evaluate `forms[next..]`, ignoring the value of the previous form.
+    Sequence(Rc<[SExpr]>, usize, EnvRef),
+    /// The operator and operands of a call, left to right.
+    Call {
+        form: Rc<[SExpr]>,
+        values: Vec<Spanned<Value>>,
+        env: EnvRef,
+        span: Span,
+    },
+    /// Bind the value of `(define name expr)`.
+    Define(String, EnvRef),
+    /// Assign the value of `(set! name expr)` to the variable at the span.
+    Set(String, EnvRef, Span),
+    /// The `before` thunk of a `dynamic-wind` returned: call the body.
+    Wind {
+        before: Spanned<Value>,
+        thunk: Spanned<Value>,
+        after: Spanned<Value>,
+        span: Span,
+    },
+    /// The body of a `dynamic-wind` returned: leave it and call `after`.
+    Unwind(Rc<Wind>, Span),
+    /// Ignore the value and return this one instead.
+    Value(Value),
+    /// A thunk called on the way to a continuation returned: call the
+    /// remaining ones, last first, then pass `value` to the continuation.
+    Throw {
+        k: Rc<Continuation>,
+        value: Value,
+        thunks: Vec<(Spanned<Value>, Option<Rc<Wind>>)>,
+        span: Span,
+    },
+    /// Code from another input was called at the span, in the input being
+    /// evaluated. Its errors are reported there, prefixed with the context.
+    Within(String, Span),
+}
+
+impl Default for Interpreter {
+    fn default() -> Interpreter {
+        let mut heap = Heap::default();
+        let core = heap.new_env(None);
+        primitives::install(heap.env_mut(core));
+        let mut interpreter = Interpreter {
+            heap,
+            global: core,
+            core,
+            macros: HashMap::new(),
+            output: String::new(),
+            gensyms: 0,
+            winds: None,
+            outer_winds: Vec::new(),
+            suspended: Vec::new(),
+            source: Source::default(),
+            read: 0,
+        };
+        if let Err(e) = interpreter.eval_source(prelude::SOURCE) {
+            panic!("Error in the Lisp prelude:\n{}", e);
+        }
+        let global = interpreter.heap.env(core).clone();
+        interpreter.global = interpreter.heap.alloc(Object::Env(global));
+        interpreter
+    }
+}
+
+impl Interpreter {
+    /// Reads the forms in `input` and expands the macro calls in them,
+    /// without evaluating anything. Errors are rendered against `input`.
+    pub fn expand_source(&mut self, input: &str) -> Result<Vec<SExpr>, String> {
+        self.with_source(input, |interpreter, forms| {
+            forms.iter().map(|form| interpreter.expand(form)).collect()
+        })
+    }
+
+    /// Evaluates the forms in `input` in the global environment, in order,
+    /// and returns the value of the last one. Each form is expanded right
+    /// before it is evaluated, so it can use the macros defined before it.
+    pub fn eval_source(&mut self, input: &str) -> Result<Value, String> {
+        self.with_source(input, |interpreter, forms| {
+            let mut value = Value::Void;
+            for form in forms {
+                let expanded = interpreter.expand(&form)?;
+                value = interpreter.eval(&expanded, interpreter.global).map_err(|e| {
+                    // Code from another input reached without a call in
+                    // this one, like a continuation, fails at the form.
+                    match interpreter.source.contains(e.span()) {
+                        true => e,
+                        false => runtime_err_within("In code from another input", e, form.span),
+                    }
+                })?;
+            }
+            Ok(value)
+        })
+    }
+
+    /// Reads the forms in `input` and runs `f` on them, with `input` as the
+    /// input being evaluated. Errors are rendered against `input`.
+    fn with_source<T>(
+        &mut self,
+        input: &str,
+        f: impl FnOnce(&mut Interpreter, Vec<SExpr>) -> Result<T, RuntimeError>,
+    ) -> Result<T, String> {
+        let source = Source {
+            start: self.read,
+            length: input.chars().count(),
+        };
+        let forms = read(input)?.iter().map(|form| shift(form, source.start)).collect();
+        self.read += source.length + 1;
+        let outer = std::mem::replace(&mut self.source, source);
+        let result = f(self, forms);
+        self.source = outer;
+        result.map_err(|e| {
+            let span = e.span().unshift(source.start);
+            runtime_error_to_string(e.at(span), input)
+        })
+    }
+
+    pub fn eval(&mut self, expr: &SExpr, env: EnvRef) -> Result<Value, RuntimeError> {
+        let forms: Rc<[SExpr]> = Rc::new([expr.clone()]);
+        self.execute(State::Eval(forms, 0, env), Vec::new())
+    }
+
+    /// Applies `f` to already evaluated arguments. `span` is the span of the
+    /// whole call. If `f` comes from another input, its errors are reported
+    /// at `span`, prefixed with `context`.
+    pub fn apply_within(
+        &mut self,
+        context: &str,
+        f: Spanned<Value>,
+        args: &[Spanned<Value>],
+        span: Span,
+    ) -> Result<Value, RuntimeError> {
+        let within = Frame::Within(context.to_string(), span);
+        self.execute(State::Apply(f, args.to_vec(), span), vec![within])
+    }
+
+    /// Runs the evaluator from `state` on `stack`. Continuations captured
+    /// meanwhile end where this evaluation ends.
+    fn execute(&mut self, mut state: State, mut stack: Vec<Frame>) -> Result<Value, RuntimeError> {
+        self.outer_winds.push(self.winds.clone());
+        let result = loop {
+            if self.heap.is_due() {
+                let mut marker = self.roots();
+                state.trace(&mut marker);
+                stack.trace(&mut marker);
+                self.heap.collect(marker);
+            }
+            let next = match state {
+                State::Eval(forms, index, env) => self.eval_step(&forms, index, env, &mut stack),
+                State::Apply(f, args, span) => self.apply_step(f, args, span, &mut stack),
+                State::Return(value) => match stack.pop() {
+                    Some(frame) => self.resume(frame, value, &mut stack),
+                    None => break Ok(value),
+                },
+            };
+            state = match next {
+                Ok(state) => state,
+                Err(e) => break Err(self.locate(e, &stack)),
+            };
+        };
+        // Errors leave every `dynamic-wind` they were raised in, without
+        // calling the `after` thunks.
+        self.winds = self.outer_winds.pop().unwrap();
+        result
+    }
+
+    /// Errors raised by code from another input are reported at the call
+    /// that led to it from the input being evaluated.
+    fn locate(&self, e: RuntimeError, stack: &[Frame]) -> RuntimeError {
+        if self.source.contains(e.span()) {
+            return e;
+        }
+        let within = stack.iter().rev().find_map(|frame| match frame {
+            Frame::Within(context, span) if self.source.contains(*span) => Some((context, *span)),
+            _ => None,
+        });
+        match within {
+            Some((context, span)) => runtime_err_within(context, e, span),
+            None => e,
+        }
+    }
+
+    /// Frees the objects that no evaluation in progress can reach. Between
+    /// two steps, the state and stack of the innermost evaluation are roots
+    /// too, so this is only called by primitives, whose callers' stacks are
+    /// suspended.
+    pub fn collect_garbage(&mut self) {
+        let marker = self.roots();
+        self.heap.collect(marker);
+    }
+
+    /// Marks the values reachable from outside the stack of the innermost
+    /// evaluation.
+    fn roots(&self) -> Marker {
+        let mut marker = Marker::default();
+        marker.mark(self.global);
+        marker.mark(self.core);
+        for &transformer in self.macros.values() {
+            marker.mark(transformer);
+        }
+        self.winds.trace(&mut marker);
+        self.outer_winds.trace(&mut marker);
+        for (stack, args) in &self.suspended {
+            stack.trace(&mut marker);
+            args.trace(&mut marker);
+        }
+        marker
+    }
+
+    fn eval_step(
+        &mut self,
+        forms: &Rc<[SExpr]>,
+        index: usize,
+        env: EnvRef,
+        stack: &mut Vec<Frame>,
+    ) -> Result<State, RuntimeError> {
+        let expr = &forms[index];
+        let value = match &expr.item {
+            Expr::Number(n) => Value::Number(n.clone()),
+            Expr::String(s) => Value::String(s.clone()),
+            Expr::Char(c) => Value::Char(*c),
+            Expr::Bool(b) => Value::Bool(*b),
+            Expr::Nil => Value::Nil,
+            Expr::Symbol(name) => self.heap.lookup(env, name).ok_or_else(|| {
+                runtime_err(&format!("Unbound variable '{}'", name), expr.span)
+            })?,
+            Expr::Quote(datum) => quote(&mut self.heap, datum),
+            Expr::List(items) => return self.eval_list(items, expr.span, env, stack),
+            Expr::DottedList(_, _) => {
+                return Err(runtime_err("Cannot evaluate a dotted list", expr.span))
+            }
+        };
+        Ok(State::Return(value))
+    }
+
+    fn eval_list(
+        &mut self,
+        items: &Rc<[SExpr]>,
+        span: Span,
+        env: EnvRef,
+        stack: &mut Vec<Frame>,
+    ) -> Result<State, RuntimeError> {
+        let (head, args) = match items.split_first() {
+            Some(split) => split,
+            None => return Err(runtime_err("Cannot evaluate the empty list", span)),
+        };
+        if let Expr::Symbol(name) = &head.item {
+            let value = match name.as_str() {
+                "quote" => {
+                    expect_args(args, 1, span, "quote")?;
+                    quote(&mut self.heap, &args[0])
+                }
+                "unquote" | "unquote-splicing" => {
+                    return Err(runtime_err("Unquote outside of a quasiquote", span));
+                }
+                "if" => {
+                    if args.len() != 2 && args.len() != 3 {
+                        let msg = format!("'if' expects 2 or 3 arguments, got {}", args.len());
+                        return Err(runtime_err(&msg, span));
+                    }
+                    stack.push(Frame::If(items.clone(), env));
+                    return Ok(State::Eval(items.clone(), 1, env));
+                }
+                "begin" => return Ok(sequence(items, 1, env, stack)),
+                "define" => return self.eval_define(items, span, env, stack),
+                "set!" => {
+                    expect_args(args, 2, span, "set!")?;
+                    let name = match &args[0].item {
+                        Expr::Symbol(name) => name.clone(),
+                        _ => return Err(runtime_err("Expected a variable", args[0].span)),
+                    };
+                    stack.push(Frame::Set(name, env, args[0].span));
+                    return Ok(State::Eval(items.clone(), 2, env));
+                }
+                "defmacro" => self.eval_defmacro(items, span, env)?,
+                "lambda" => {
+                    let params = match args.first() {
+                        Some(params) => parameters(params)?,
+                        None => return Err(runtime_err("'lambda' expects a parameter list", span)),
+                    };
+                    expect_body(items, 2, span)?;
+                    closure(&mut self.heap, None, params, items, 2, env)
+                }
+                _ => return Ok(call(items, span, env, stack)),
+            };
+            return Ok(State::Return(value));
+        }
+        Ok(call(items, span, env, stack))
+    }
+
+    /// `(define name expr)` or `(define (name params...) body...)`, which is
+    /// short for `(define name (lambda (params...) body...))`.
+    fn eval_define(
+        &mut self,
+        items: &Rc<[SExpr]>,
+        span: Span,
+        env: EnvRef,
+        stack: &mut Vec<Frame>,
+    ) -> Result<State, RuntimeError> {
+        let args = &items[1..];
+        let target = args
+            .first()
+            .ok_or_else(|| runtime_err("'define' expects a name", span))?;
+        if let Expr::Symbol(name) = &target.item {
+            expect_args(args, 2, span, "define")?;
+            stack.push(Frame::Define(name.clone(), env));
+            return Ok(State::Eval(items.clone(), 2, env));
+        }
+        let (name, params) = signature(target)?;
+        expect_body(items, 2, span)?;
+        let f = closure(&mut self.heap, Some(name.clone()), params, items, 2, env);
+        self.heap.define(env, &name, f);
+        Ok(State::Return(Value::Symbol(name)))
+    }
+
+    /// `(defmacro name params body...)` defines a macro whose transformer
+    /// is `(lambda params body...)`.
+    fn eval_defmacro(
+        &mut self,
+        items: &Rc<[SExpr]>,
+        span: Span,
+        env: EnvRef,
+    ) -> Result<Value, RuntimeError> {
+        let name = match items.get(1) {
+            Some(SExpr { item: Expr::Symbol(name), .. }) => name.clone(),
+            Some(e) => return Err(runtime_err("Expected a macro name", e.span)),
+            None => return Err(runtime_err("'defmacro' expects a name", span)),
+        };
+        let params = match items.get(2) {
+            Some(params) => parameters(params)?,
+            None => return Err(runtime_err("'defmacro' expects a parameter list", span)),
+        };
+        expect_body(items, 3, span)?;
+        let transformer = Closure {
+            name: Some(name.clone()),
+            params,
+            form: items.clone(),
+            body: 3,
+            env,
+        };
+        let transformer = self.heap.alloc(Object::Closure(transformer));
+        self.macros.insert(name.clone(), transformer);
+        Ok(Value::Symbol(name))
+    }
+
+    fn apply_step(
+        &mut self,
+        f: Spanned<Value>,
+        args: Vec<Spanned<Value>>,
+        span: Span,
+        stack: &mut Vec<Frame>,
+    ) -> Result<State, RuntimeError> {
+        match f.item {
+            Value::Primitive(p) => {
+                self.suspended.push((std::mem::take(stack), args.clone()));
+                let result = (p.func)(self, &args, span);
+                *stack = self.suspended.pop().unwrap().0;
+                Ok(State::Return(result?))
+            }
+            Value::Closure(closure) => {
+                let env = bind_arguments(&mut self.heap, closure, &args, span)?;
+                let closure = self.heap.closure(closure);
+                // A tail call keeps the frame of the call it replaces, so
+                // that loops still run in constant space.
+                let entered = self.source.contains(span)
+                    && !self.source.contains(closure.form[closure.body].span)
+                    && !matches!(stack.last(), Some(Frame::Within(..)));
+                if entered {
+                    let context = match &closure.name {
+                        Some(name) => format!("In '{}'", name),
+                        None => "In a procedure".to_string(),
+                    };
+                    stack.push(Frame::Within(context, span));
+                }
+                Ok(sequence(&closure.form.clone(), closure.body, env, stack))
+            }
+            Value::Control(Control::CallCC) => {
+                let [f] = expect_values::<1>(args, span)?;
+                let k = Continuation {
+                    frames: stack.clone(),
+                    winds: self.winds.clone(),
+                };
+                let k = Spanned::new(Value::Continuation(Rc::new(k)), span);
+                Ok(State::Apply(f, vec![k], span))
+            }
+            Value::Control(Control::DynamicWind) => {
+                let [before, thunk, after] = expect_values::<3>(args, span)?;
+                let apply_before = State::Apply(before.clone(), Vec::new(), span);
+                stack.push(Frame::Wind {
+                    before,
+                    thunk,
+                    after,
+                    span,
+                });
+                Ok(apply_before)
+            }
+            Value::Continuation(k) => {
+                let [value] = expect_values::<1>(args, span)?;
+                let mut thunks = transition(&self.winds, &k.winds);
+                thunks.reverse();
+                Ok(self.throw(k, value.item, thunks, span, stack))
+            }
+            v => {
+                let msg = format!(
+                    "Expected a procedure, got {} {}",
+                    v.type_name(),
+                    self.heap.show(&v)
+                );
+                Err(runtime_err(&msg, f.span))
+            }
+        }
+    }
+
+    /// Passes `value` to the work waiting for it in `frame`.
+    fn resume(
+        &mut self,
+        frame: Frame,
+        value: Value,
+        stack: &mut Vec<Frame>,
+    ) -> Result<State, RuntimeError> {
+        let state = match frame {
+            Frame::If(form, env) => {
+                if value.is_true() {
+                    State::Eval(form, 2, env)
+                } else if form.len() == 4 {
+                    State::Eval(form, 3, env)
+                } else {
+                    State::Return(Value::Void)
+                }
+            }
+            Frame::Sequence(forms, next, env) => sequence(&forms, next, env, stack),
+            Frame::Call {
+                form,
+                mut values,
+                env,
+                span,
+            } => {
+                values.push(Spanned::new(value, form[values.len()].span));
+                let next = values.len();
+                if next < form.len() {
+                    stack.push(Frame::Call {
+                        form: form.clone(),
+                        values,
+                        env,
+                        span,
+                    });
+                    State::Eval(form, next, env)
+                } else {
+                    let f = values.remove(0);
+                    State::Apply(f, values, span)
+                }
+            }
+            Frame::Define(name, env) => {
+                self.heap.define(env, &name, value);
+                State::Return(Value::Symbol(name))
+            }
+            Frame::Set(name, env, span) => {
+                if !self.heap.assign(env, &name, value) {
+                    let msg = format!("Unbound variable '{}'", name);
+                    return Err(runtime_err(&msg, span));
+                }
+                State::Return(Value::Void)
+            }
+            Frame::Wind {
+                before,
+                thunk,
+                after,
+                span,
+            } => {
+                let wind = Rc::new(Wind {
+                    before,
+                    after,
+                    depth: depth(&self.winds) + 1,
+                    parent: self.winds.take(),
+                });
+                self.winds = Some(wind.clone());
+                stack.push(Frame::Unwind(wind, span));
+                State::Apply(thunk, Vec::new(), span)
+            }
+            Frame::Unwind(wind, span) => {
+                self.winds = wind.parent.clone();
+                stack.push(Frame::Value(value));
+                State::Apply(wind.after.clone(), Vec::new(), span)
+            }
+            Frame::Value(value) => State::Return(value),
+            Frame::Within(..) => State::Return(value),
+            Frame::Throw {
+                k,
+                value,
+                thunks,
+                span,
+            } => self.throw(k, value, thunks, span, stack),
+        };
+        Ok(state)
+    }
+
+    /// Calls the next of `thunks`, or, once they have all been called,
+    /// replaces the stack by the continuation `k` and passes it `value`.
+    fn throw(
+        &mut self,
+        k: Rc<Continuation>,
+        value: Value,
+        mut thunks: Vec<(Spanned<Value>, Option<Rc<Wind>>)>,
+        span: Span,
+        stack: &mut Vec<Frame>,
+    ) -> State {
+        match thunks.pop() {
+            Some((thunk, winds)) => {
+                self.winds = winds;
+                stack.push(Frame::Throw {
+                    k,
+                    value,
+                    thunks,
+                    span,
+                });
+                State::Apply(thunk, Vec::new(), span)
+            }
+            None => {
+                *stack = k.frames.clone();
+                self.winds = k.winds.clone();
+                State::Return(value)
+            }
+        }
+    }
+}
+
+/// `expr` with all of its spans shifted by `offset`.
+fn shift(expr: &SExpr, offset: usize) -> SExpr {
+    let item = match &expr.item {
+        Expr::List(items) => Expr::List(items.iter().map(|e| shift(e, offset)).collect()),
+        Expr::DottedList(items, tail) => Expr::DottedList(
+            items.iter().map(|e| shift(e, offset)).collect(),
+            Box::new(shift(tail, offset)),
+        ),
+        Expr::Quote(e) => Expr::Quote(Box::new(shift(e, offset))),
+        item => item.clone(),
+    };
+    SExpr::new(item, expr.span.shift(offset))
+}
+
+fn read(input: &str) -> Result<Vec<SExpr>, String> {
+    let tokens = tokenize(input).map_err(|e| tokenize_error_to_string(e, input))?;
+    parse_program(&tokens).map_err(|e| parse_error_to_string(e, input))
+}
+
+impl Trace for State {
+    fn trace(&self, marker: &mut Marker) {
+        match self {
+            State::Eval(_, _, env) => marker.mark(*env),
+            State::Apply(f, args, _) => {
+                f.trace(marker);
+                args.trace(marker);
+            }
+            State::Return(value) => value.trace(marker),
+        }
+    }
+}
+
+impl Trace for Frame {
+    fn trace(&self, marker: &mut Marker) {
+        match self {
+            Frame::If(_, env) | Frame::Sequence(_, _, env) => marker.mark(*env),
+            Frame::Define(_, env) | Frame::Set(_, env, _) => marker.mark(*env),
+            Frame::Call { values, env, .. } => {
+                values.trace(marker);
+                marker.mark(*env);
+            }
+            Frame::Wind {
+                before,
+                thunk,
+                after,
+                ..
+            } => {
+                before.trace(marker);
+                thunk.trace(marker);
+                after.trace(marker);
+            }
+            Frame::Unwind(wind, _) => wind.trace(marker),
+            Frame::Value(value) => value.trace(marker),
+            Frame::Throw {
+                k, value, thunks, ..
+            } => {
+                if marker.first_visit(k) {
+                    k.trace(marker);
+                }
+                value.trace(marker);
+                for (thunk, winds) in thunks {
+                    thunk.trace(marker);
+                    winds.trace(marker);
+                }
+            }
+            Frame::Within(..) => {}
+        }
+    }
+}
+
+impl Trace for Continuation {
+    fn trace(&self, marker: &mut Marker) {
+        self.frames.trace(marker);
+        self.winds.trace(marker);
+    }
+}
+
+/// Winds are shared by the continuations captured inside them, so each is
+/// traced once.
+impl Trace for Rc<Wind> {
+    fn trace(&self, marker: &mut Marker) {
+        if marker.first_visit(self) {
+            self.before.trace(marker);
+            self.after.trace(marker);
+            self.parent.trace(marker);
+        }
+    }
+}
+
+/// Starts evaluating `forms[start..]` in order, the last one in tail
+/// position. No forms yield no value.
+fn sequence(forms: &Rc<[SExpr]>, start: usize, env: EnvRef, stack: &mut Vec<Frame>) -> State {
+    if start >= forms.len() {
+        return State::Return(Value::Void);
+    }
+    if start + 1 < forms.len() {
+        stack.push(Frame::Sequence(forms.clone(), start + 1, env));
+    }
+    State::Eval(forms.clone(), start, env)
+}
+
+/// Starts evaluating the operator and the operands of a call.
+fn call(form: &Rc<[SExpr]>, span: Span, env: EnvRef, stack: &mut Vec<Frame>) -> State {
+    stack.push(Frame::Call {
+        form: form.clone(),
+        values: Vec::new(),
+        env,
+        span,
+    });
+    State::Eval(form.clone(), 0, env)
+}
+
+fn depth(winds: &Option<Rc<Wind>>) -> usize {
+    winds.as_ref().map_or(0, |wind| wind.depth)
+}
+
+/// The thunks to call when control moves from inside the `dynamic-wind`
+/// calls `from` to inside `to`, each with the calls it runs inside: the
+/// `after` thunks of the calls left, innermost first, then the `before`
+/// thunks of the calls entered, outermost first.
+fn transition(
+    from: &Option<Rc<Wind>>,
+    to: &Option<Rc<Wind>>,
+) -> Vec<(Spanned<Value>, Option<Rc<Wind>>)> {
+    let mut exits = Vec::new();
+    let mut entries = Vec::new();
+    let (mut from, mut to) = (from.clone(), to.clone());
+    loop {
+        match (&from, &to) {
+            (None, None) => break,
+            (Some(a), Some(b)) if Rc::ptr_eq(a, b) => break,
+            (Some(a), _) if a.depth >= depth(&to) => {
+                exits.push((a.after.clone(), a.parent.clone()));
+                from = a.parent.clone();
+            }
+            (_, Some(b)) => {
+                entries.push((b.before.clone(), b.parent.clone()));
+                to = b.parent.clone();
+            }
+            (Some(_), None) => unreachable!(),
+        }
+    }
+    exits.extend(entries.into_iter().rev());
+    exits
+}
+
+/// A new scope for a call of `closure`, with its parameters bound.
+fn bind_arguments(
+    heap: &mut Heap,
+    closure: Handle,
+    args: &[Spanned<Value>],
+    span: Span,
+) -> Result<EnvRef, RuntimeError> {
+    let closure = heap.closure(closure);
+    let params = &closure.params;
+    let arity_ok = match params.rest {
+        Some(_) => args.len() >= params.required.len(),
+        None => args.len() == params.required.len(),
+    };
+    if !arity_ok {
+        let at_least = if params.rest.is_some() { "at least " } else { "" };
+        let expected = arguments(params.required.len());
+        let msg = format!("Expected {}{}, got {}", at_least, expected, args.len());
+        return Err(runtime_err(&msg, span));
+    }
+    let mut scope = Env::new(Some(closure.env));
+    for (param, arg) in params.required.iter().zip(args) {
+        scope.define(param, arg.item.clone());
+    }
+    if let Some(rest) = params.rest.clone() {
+        let extra = args[params.required.len()..].iter().map(|a| a.item.clone());
+        let extra = heap.list(extra.collect());
+        scope.define(&rest, extra);
+    }
+    Ok(heap.alloc(Object::Env(scope)))
+}
+
+/// Converts a quoted expression to the value it denotes.
+pub(super) fn quote(heap: &mut Heap, datum: &SExpr) -> Value {
+    match &datum.item {
+        Expr::Number(n) => Value::Number(n.clone()),
+        Expr::String(s) => Value::String(s.clone()),
+        Expr::Char(c) => Value::Char(*c),
+        Expr::Bool(b) => Value::Bool(*b),
+        Expr::Nil => Value::Nil,
+        Expr::Symbol(name) => Value::Symbol(name.clone()),
+        Expr::List(items) => {
+            let items = items.iter().map(|item| quote(heap, item)).collect();
+            heap.list(items)
+        }
+        Expr::DottedList(items, tail) => {
+            let tail = quote(heap, tail);
+            items.iter().rev().fold(tail, |rest, item| {
+                let item = quote(heap, item);
+                heap.cons(item, rest)
+            })
+        }
+        Expr::Quote(datum) => {
+            let datum = quote(heap, datum);
+            heap.list(vec![Value::Symbol("quote".to_string()), datum])
+        }
+    }
+}
+
+/// A closure whose body is `form[body..]`.
+fn closure(
+    heap: &mut Heap,
+    name: Option<String>,
+    params: Params,
+    form: &Rc<[SExpr]>,
+    body: usize,
+    env: EnvRef,
+) -> Value {
+    let closure = Closure {
+        name,
+        params,
+        form: form.clone(),
+        body,
+        env,
+    };
+    Value::Closure(heap.alloc(Object::Closure(closure)))
+}
+
+/// Parses a parameter list: `(a b)`, `(a b . rest)` or a single symbol
+/// `rest` that receives all arguments as a list.
+fn parameters(spec: &SExpr) -> Result<Params, RuntimeError> {
+    let (required, rest) = match &spec.item {
+        Expr::Symbol(rest) => (&[][..], Some(rest.clone())),
+        Expr::List(items) => (&items[..], None),
+        Expr::Nil => (&[][..], None),
+        Expr::DottedList(items, tail) => (&items[..], Some(parameter_name(tail)?)),
+        _ => return Err(runtime_err("Expected a parameter list", spec.span)),
+    };
+    let required = required.iter().map(parameter_name).collect::<Result<_, _>>()?;
+    Ok(Params { required, rest })
+}
+
+fn parameter_name(param: &SExpr) -> Result<String, RuntimeError> {
+    match &param.item {
+        Expr::Symbol(name) => Ok(name.clone()),
+        _ => Err(runtime_err("Expected a parameter name", param.span)),
+    }
+}
+
+/// Splits the signature `(name params...)` or `(name params... . rest)` of
+/// a function definition.
+fn signature(target: &SExpr) -> Result<(String, Params), RuntimeError> {
+    let (items, tail) = match &target.item {
+        Expr::List(items) => (&items[..], None),
+        Expr::DottedList(items, tail) => (&items[..], Some(tail)),
+        _ => return Err(runtime_err("Expected a name", target.span)),
+    };
+    let name = match items.first() {
+        Some(SExpr { item: Expr::Symbol(name), .. }) => name.clone(),
+        _ => return Err(runtime_err("Expected a function name", target.span)),
+    };
+    let rest = &items[1..];
+    let params = match tail {
+        None => SExpr::new(Expr::List(rest.into()), target.span),
+        Some(tail) if rest.is_empty() => *tail.clone(),
+        Some(tail) => SExpr::new(Expr::DottedList(rest.to_vec(), tail.clone()), target.span),
+    };
+    Ok((name, parameters(&params)?))
+}
+
+/// Checks that a function body starts at `form[body]`.
+fn expect_body(form: &[SExpr], body: usize, span: Span) -> Result<(), RuntimeError> {
+    if form.len() <= body {
+        return Err(runtime_err("Expected a function body", span));
+    }
+    Ok(())
+}
+
+fn expect_args(args: &[SExpr], n: usize, span: Span, form: &str) -> Result<(), RuntimeError> {
+    if args.len() != n {
+        let msg = format!("'{}' expects {}, got {}", form, arguments(n), args.len());
+        return Err(runtime_err(&msg, span));
+    }
+    Ok(())
+}
+
+/// The arguments of a control primitive, which takes exactly `N`.
+fn expect_values<const N: usize>(
+    args: Vec<Spanned<Value>>,
+    span: Span,
+) -> Result<[Spanned<Value>; N], RuntimeError> {
+    let n = args.len();
+    args.try_into().map_err(|_| {
+        let msg = format!("Expected {}, got {}", arguments(N), n);
+        runtime_err(&msg, span)
+    })
+}
+
+/// "1 argument", "2 arguments", ...
+pub(super) fn arguments(n: usize) -> String {
+    match n {
+        1 => "1 argument".to_string(),
+        n => format!("{} arguments", n),
+    }
+}
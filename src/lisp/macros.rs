@@ -0,0 +1,130 @@
+use super::ast::Expr;
+use super::ast::SExpr;
+use super::heap::Handle;
+use super::heap::Heap;
+use super::interpreter::quote;
+use super::interpreter::Interpreter;
+use super::value::Value;
+
+use crate::parsing_utils::runtime_err;
+use crate::parsing_utils::RuntimeError;
+use crate::parsing_utils::Span;
+use crate::parsing_utils::Spanned;
+
+use std::collections::HashSet;
+
+impl Interpreter {
+    /// Replaces every macro call in `expr` by its expansion, until no macro
+    /// calls are left. Quoted data and parameter lists are not expanded.
+    pub fn expand(&mut self, expr: &SExpr) -> Result<SExpr, RuntimeError> {
+        let items = match &expr.item {
+            Expr::List(items) => items,
+            _ => return Ok(expr.clone()),
+        };
+        let head = match items.first() {
+            Some(SExpr {
+                item: Expr::Symbol(name),
+                ..
+            }) => name.as_str(),
+            _ => return self.expand_items(items, 0, expr.span),
+        };
+        if let Some(&transformer) = self.macros.get(head) {
+            let expansion = self.expand_once(head, transformer, &items[1..], expr.span)?;
+            return self.expand(&expansion);
+        }
+        let keep = match head {
+            "quote" => return Ok(expr.clone()),
+            "lambda" => 2,
+            "define" if !matches!(items.get(1).map(|e| &e.item), Some(Expr::Symbol(_))) => 2,
+            "defmacro" => 3,
+            _ => 1,
+        };
+        self.expand_items(items, keep, expr.span)
+    }
+
+    /// Expands the items of a list form after the first `keep` ones.
+    fn expand_items(
+        &mut self,
+        items: &[SExpr],
+        keep: usize,
+        span: Span,
+    ) -> Result<SExpr, RuntimeError> {
+        let keep = keep.min(items.len());
+        let mut expanded = items[..keep].to_vec();
+        for item in &items[keep..] {
+            expanded.push(self.expand(item)?);
+        }
+        Ok(SExpr::new(Expr::List(expanded.into()), span))
+    }
+
+    /// Calls a macro transformer on the unevaluated argument forms. The
+    /// transformer was defined in an earlier input, so its errors are
+    /// reported at the macro call.
+    fn expand_once(
+        &mut self,
+        name: &str,
+        transformer: Handle,
+        args: &[SExpr],
+        span: Span,
+    ) -> Result<SExpr, RuntimeError> {
+        let args: Vec<Spanned<Value>> = args
+            .iter()
+            .map(|arg| Spanned::new(quote(&mut self.heap, arg), arg.span))
+            .collect();
+        let context = format!("In the expansion of '{}'", name);
+        let f = Spanned::new(Value::Closure(transformer), span);
+        let expansion = self.apply_within(&context, f, &args, span)?;
+        to_expr(&self.heap, &expansion, span, &mut HashSet::new())
+    }
+}
+
+/// Converts the value a macro returned back to code. The expansion has no
+/// source text of its own, so all of it gets the span of the macro call.
+/// `open` holds the pairs of the lists being converted around `value`; code
+/// cannot lead back into one of them.
+fn to_expr(
+    heap: &Heap,
+    value: &Value,
+    span: Span,
+    open: &mut HashSet<Handle>,
+) -> Result<SExpr, RuntimeError> {
+    let item = match value {
+        Value::Nil => Expr::Nil,
+        Value::Bool(b) => Expr::Bool(*b),
+        Value::Number(n) => Expr::Number(n.clone()),
+        Value::Symbol(name) => Expr::Symbol(name.clone()),
+        Value::String(s) => Expr::String(s.clone()),
+        Value::Char(c) => Expr::Char(*c),
+        Value::Pair(_) => {
+            let mut items = Vec::new();
+            let mut spine = Vec::new();
+            let mut v = value;
+            while let Value::Pair(pair) = v {
+                if !open.insert(*pair) {
+                    let msg = "Macro expansion contains a circular list, which is not code";
+                    return Err(runtime_err(msg, span));
+                }
+                spine.push(*pair);
+                let (car, cdr) = heap.pair(*pair);
+                items.push(to_expr(heap, car, span, open)?);
+                v = cdr;
+            }
+            let item = match v {
+                Value::Nil => Expr::List(items.into()),
+                tail => Expr::DottedList(items, Box::new(to_expr(heap, tail, span, open)?)),
+            };
+            for pair in spine {
+                open.remove(&pair);
+            }
+            item
+        }
+        v => {
+            let msg = format!(
+                "Macro expansion contains a {}, which is not code",
+                v.type_name()
+            );
+            return Err(runtime_err(&msg, span));
+        }
+    };
+    Ok(SExpr::new(item, span))
+}
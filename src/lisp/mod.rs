@@ -1,8 +1,18 @@
+
+
 // include module from same folder
 mod evaluator;
 mod tokenizer;
 mod parser;
 mod ast;
+mod bigint;
+mod heap;
+mod interpreter;
+mod macros;
+mod number;
+mod prelude;
+mod primitives;
+mod value;
 
 
 pub use evaluator::Lisp;
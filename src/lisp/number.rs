@@ -0,0 +1,362 @@
+use super::bigint::BigInt;
+
+use std::cmp::Ordering;
+use std::fmt::{self, Display, Formatter};
+use std::ops::{Add, Mul, Neg, Sub};
+
+/// A Lisp number. Exact numbers are integers of any size and fractions;
+/// inexact ones are floats. Arithmetic on exact numbers stays exact, and an
+/// inexact operand makes the result inexact, as in Scheme.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum Number {
+    /// An exact integer that fits in an `i64`, the common case.
+    Int(i64),
+    /// An exact integer that does not fit in an `i64`.
+    Big(BigInt),
+    /// An exact fraction in lowest terms, with a denominator above 1.
+    Ratio(BigInt, BigInt),
+    Real(f64),
+}
+
+use Number::*;
+
+impl Number {
+    /// Reads an integer (`-12`), a fraction (`1/3`) or a float (`1.5`,
+    /// `.5`, `1e10`, `+inf.0`, `+nan.0`).
+    pub fn parse(s: &str) -> Option<Number> {
+        match s {
+            "+inf.0" => return Some(Real(f64::INFINITY)),
+            "-inf.0" => return Some(Real(f64::NEG_INFINITY)),
+            "+nan.0" | "-nan.0" => return Some(Real(f64::NAN)),
+            _ => {}
+        }
+        if let Some((numerator, denominator)) = s.split_once('/') {
+            let denominator = BigInt::parse(denominator).filter(|_| is_unsigned(denominator))?;
+            if denominator.is_zero() {
+                return None;
+            }
+            return Some(ratio(BigInt::parse(numerator)?, denominator));
+        }
+        if let Some(n) = BigInt::parse(s) {
+            return Some(integer(n));
+        }
+        is_decimal(s).then(|| s.parse().ok().map(Real)).flatten()
+    }
+
+    pub fn is_exact(&self) -> bool {
+        !matches!(self, Real(_))
+    }
+
+    pub fn is_integer(&self) -> bool {
+        match self {
+            Int(_) | Big(_) => true,
+            Ratio(_, _) => false,
+            Real(f) => f.fract() == 0.0,
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Int(n) => *n as f64,
+            Big(n) => n.to_f64(),
+            Ratio(n, d) => n.to_f64() / d.to_f64(),
+            Real(f) => *f,
+        }
+    }
+
+    /// The exact number equal to a float, which exists unless it is
+    /// infinite or not a number.
+    pub fn to_exact(&self) -> Option<Number> {
+        let f = match self {
+            Real(f) if !f.is_finite() => return None,
+            Real(f) => *f,
+            n => return Some(n.clone()),
+        };
+        // A finite float is mantissa * 2^exponent.
+        let bits = f.to_bits();
+        let exponent = ((bits >> 52) & 0x7ff) as i32;
+        let mut mantissa = (bits & ((1 << 52) - 1)) as i64;
+        let exponent = if exponent == 0 {
+            -1074
+        } else {
+            mantissa |= 1 << 52;
+            exponent - 1075
+        };
+        if f < 0.0 {
+            mantissa = -mantissa;
+        }
+        let mantissa = BigInt::from(mantissa);
+        Some(if exponent >= 0 {
+            integer(&mantissa * &BigInt::pow2(exponent as u32))
+        } else {
+            ratio(mantissa, BigInt::pow2(-exponent as u32))
+        })
+    }
+
+    pub fn to_inexact(&self) -> Number {
+        Real(self.to_f64())
+    }
+
+    /// Numeric comparison, exact if both numbers are. `None` if one of them
+    /// is not a number.
+    pub fn compare(&self, other: &Number) -> Option<Ordering> {
+        match (self, other) {
+            (Int(a), Int(b)) => Some(a.cmp(b)),
+            (a, b) if a.is_exact() && b.is_exact() => {
+                let (n1, d1) = a.fraction();
+                let (n2, d2) = b.fraction();
+                Some((&n1 * &d2).cmp(&(&n2 * &d1)))
+            }
+            (a, b) => a.to_f64().partial_cmp(&b.to_f64()),
+        }
+    }
+
+    /// `self / other`, or `None` if `other` is an exact zero.
+    pub fn checked_div(&self, other: &Number) -> Option<Number> {
+        if other.is_exact() && other.is_zero() {
+            return None;
+        }
+        if !self.is_exact() || !other.is_exact() {
+            return Some(Real(self.to_f64() / other.to_f64()));
+        }
+        let (n1, d1) = self.fraction();
+        let (n2, d2) = other.fraction();
+        Some(ratio(&n1 * &d2, &d1 * &n2))
+    }
+
+    /// The integer quotient, rounded towards zero, and the remainder of two
+    /// integers. `None` if one of them is not an integer, or the divisor is
+    /// zero.
+    pub fn quotient_remainder(&self, other: &Number) -> Option<(Number, Number)> {
+        if !self.is_integer() || !other.is_integer() || other.is_zero() {
+            return None;
+        }
+        match (self, other) {
+            (Int(a), Int(b)) if a.checked_div(*b).is_some() => Some((Int(a / b), Int(a % b))),
+            (a, b) if a.is_exact() && b.is_exact() => {
+                let (q, r) = a.fraction().0.div_rem(&b.fraction().0);
+                Some((integer(q), integer(r)))
+            }
+            (a, b) => {
+                let (a, b) = (a.to_f64(), b.to_f64());
+                Some((Real((a / b).trunc()), Real(a % b)))
+            }
+        }
+    }
+
+    fn is_zero(&self) -> bool {
+        match self {
+            Int(n) => *n == 0,
+            Big(_) | Ratio(_, _) => false,
+            Real(f) => *f == 0.0,
+        }
+    }
+
+    /// The numerator and denominator of an exact number.
+    fn fraction(&self) -> (BigInt, BigInt) {
+        match self {
+            Int(n) => (BigInt::from(*n), BigInt::from(1)),
+            Big(n) => (n.clone(), BigInt::from(1)),
+            Ratio(n, d) => (n.clone(), d.clone()),
+            Real(_) => panic!("inexact number has no exact fraction"),
+        }
+    }
+}
+
+fn integer(n: BigInt) -> Number {
+    match n.to_i64() {
+        Some(n) => Int(n),
+        None => Big(n),
+    }
+}
+
+/// The fraction `numerator / denominator` in lowest terms, or an integer if
+/// the denominator divides the numerator. The denominator is not zero.
+fn ratio(numerator: BigInt, denominator: BigInt) -> Number {
+    let gcd = numerator.gcd(&denominator);
+    let (mut n, mut d) = (numerator.div_rem(&gcd).0, denominator.div_rem(&gcd).0);
+    if d.is_negative() {
+        n = -&n;
+        d = -&d;
+    }
+    if d == BigInt::from(1) {
+        integer(n)
+    } else {
+        Ratio(n, d)
+    }
+}
+
+fn is_unsigned(s: &str) -> bool {
+    !s.starts_with(['+', '-'])
+}
+
+/// `digits [. digits] [e [sign] digits]`, with a sign in front and at least
+/// one digit before the exponent.
+fn is_decimal(s: &str) -> bool {
+    let s = s.strip_prefix(['+', '-']).unwrap_or(s);
+    let (mantissa, exponent) = match s.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, Some(exponent)),
+        None => (s, None),
+    };
+    let (whole, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+    let exponent_ok = match exponent {
+        Some(e) => {
+            let e = e.strip_prefix(['+', '-']).unwrap_or(e);
+            !e.is_empty() && digits(e)
+        }
+        None => true,
+    };
+    digits(whole) && digits(fraction) && whole.len() + fraction.len() > 0 && exponent_ok
+}
+
+/// Applies an operation on exact numbers, unless one operand is inexact, in
+/// which case the result is inexact too.
+fn combine(
+    a: &Number,
+    b: &Number,
+    exact: fn((BigInt, BigInt), (BigInt, BigInt)) -> Number,
+    inexact: fn(f64, f64) -> f64,
+) -> Number {
+    if a.is_exact() && b.is_exact() {
+        exact(a.fraction(), b.fraction())
+    } else {
+        Real(inexact(a.to_f64(), b.to_f64()))
+    }
+}
+
+impl Add for &Number {
+    type Output = Number;
+
+    fn add(self, other: &Number) -> Number {
+        if let (Int(a), Int(b)) = (self, other) {
+            if let Some(sum) = a.checked_add(*b) {
+                return Int(sum);
+            }
+        }
+        combine(
+            self,
+            other,
+            |(n1, d1), (n2, d2)| ratio(&(&n1 * &d2) + &(&n2 * &d1), &d1 * &d2),
+            |a, b| a + b,
+        )
+    }
+}
+
+impl Sub for &Number {
+    type Output = Number;
+
+    fn sub(self, other: &Number) -> Number {
+        if let (Int(a), Int(b)) = (self, other) {
+            if let Some(difference) = a.checked_sub(*b) {
+                return Int(difference);
+            }
+        }
+        self + &-other
+    }
+}
+
+impl Mul for &Number {
+    type Output = Number;
+
+    fn mul(self, other: &Number) -> Number {
+        if let (Int(a), Int(b)) = (self, other) {
+            if let Some(product) = a.checked_mul(*b) {
+                return Int(product);
+            }
+        }
+        combine(
+            self,
+            other,
+            |(n1, d1), (n2, d2)| ratio(&n1 * &n2, &d1 * &d2),
+            |a, b| a * b,
+        )
+    }
+}
+
+impl Neg for &Number {
+    type Output = Number;
+
+    fn neg(self) -> Number {
+        match self {
+            Int(n) => n
+                .checked_neg()
+                .map_or_else(|| integer(-&BigInt::from(*n)), Int),
+            Big(n) => integer(-n),
+            Ratio(n, d) => Ratio(-n, d.clone()),
+            Real(f) => Real(-f),
+        }
+    }
+}
+
+/// Floats always show a decimal point, so that they read back as floats.
+impl Display for Number {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Int(n) => write!(f, "{}", n),
+            Big(n) => write!(f, "{}", n),
+            Ratio(n, d) => write!(f, "{}/{}", n, d),
+            Real(x) if x.is_nan() => write!(f, "+nan.0"),
+            Real(x) if x.is_infinite() => write!(f, "{}inf.0", if *x > 0.0 { "+" } else { "-" }),
+            Real(x) if x.fract() == 0.0 && x.abs() < 1e16 => write!(f, "{:.1}", x),
+            Real(x) if x.fract() == 0.0 => write!(f, "{:e}", x),
+            Real(x) => write!(f, "{}", x),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn n(s: &str) -> Number {
+        Number::parse(s).unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let cases = vec![
+            ("42", "42"),
+            ("-7", "-7"),
+            ("99999999999999999999", "99999999999999999999"),
+            ("6/4", "3/2"),
+            ("-6/3", "-2"),
+            ("1.5", "1.5"),
+            ("-.5", "-0.5"),
+            ("2.", "2.0"),
+            ("1e3", "1000.0"),
+            ("1e20", "1e20"),
+            ("+inf.0", "+inf.0"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(n(input).to_string(), expected, "{}", input);
+        }
+        for invalid in ["1/0", "1/-2", "1.2.3", "e5", ".", "1e", "--1", "0x10"] {
+            assert_eq!(Number::parse(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(
+            (&n("9223372036854775807") + &n("1")).to_string(),
+            "9223372036854775808"
+        );
+        assert_eq!(&n("9223372036854775808") - &n("1"), Int(i64::MAX));
+        assert_eq!((&n("1/2") + &n("1/3")).to_string(), "5/6");
+        assert_eq!(&n("1/2") + &n("1/2"), Int(1));
+        assert_eq!(&n("1/2") * &n("0.5"), Real(0.25));
+        assert_eq!(n("1").checked_div(&n("3")).unwrap().to_string(), "1/3");
+        assert_eq!(n("1.0").checked_div(&n("0")), None);
+        assert_eq!(n("1").checked_div(&n("0.0")), Some(Real(f64::INFINITY)));
+        assert_eq!(-&Int(i64::MIN), n("9223372036854775808"));
+        assert_eq!(n("1/3").compare(&n("0.34")), Some(Ordering::Less));
+        assert_eq!(n("1").compare(&n("1.0")), Some(Ordering::Equal));
+        assert_eq!(n("+nan.0").compare(&n("1")), None);
+        assert_eq!(n("0.75").to_exact(), Some(n("3/4")));
+        assert_eq!(n("-1e20").to_exact(), Some(n("-100000000000000000000")));
+        let (q, r) = n("-7").quotient_remainder(&n("2")).unwrap();
+        assert_eq!((q, r), (Int(-3), Int(-1)));
+        assert_eq!(n("7.5").quotient_remainder(&n("2")), None);
+    }
+}
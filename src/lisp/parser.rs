@@ -8,13 +8,27 @@ use super::tokenizer::SToken;
 use super::tokenizer::Token;
 
 use std::iter::Peekable;
+use std::rc::Rc;
 use std::slice::Iter;
 
+/// The names the quasiquote expansion calls `cons` and `append` by. The
+/// reader cannot read them, so programs cannot rebind them.
+pub(super) const CONS: &str = "#%cons";
+pub(super) const APPEND: &str = "#%append";
+
 type TokenIterator<'a> = Peekable<Iter<'a, SToken>>;
 
-pub(super) fn parse(tokens: &Vec<SToken>) -> Result<SExpr, ParseError> {
+/// Parses a program: a sequence of expressions, possibly empty.
+pub(super) fn parse_program(tokens: &[SToken]) -> Result<Vec<SExpr>, ParseError> {
     let mut it = tokens.iter().peekable();
-    parse_expression(&mut it)
+    let mut forms = Vec::new();
+    skip_whitespace(&mut it);
+    while it.peek().is_some() {
+        let expr = parse_expression(&mut it)?;
+        forms.push(expand_quasiquotes(&expr)?);
+        skip_whitespace(&mut it);
+    }
+    Ok(forms)
 }
 
 fn parse_expression(it: &mut TokenIterator) -> Result<SExpr, ParseError> {
@@ -22,23 +36,24 @@ fn parse_expression(it: &mut TokenIterator) -> Result<SExpr, ParseError> {
     let t = it
         .peek()
         .ok_or(parse_err("Unexpected end of input", Span::new(0, 0)))?;
-    let exp = match &t.item {
+    match &t.item {
         Token::OpenParen => parse_list(it),
         Token::Symbol(_) => parse_symbol(it),
         Token::Number(_) => parse_number(it),
-        //Token::String(_) => parse_string(it),
-        //Token::Quote => parse_quote(it),
+        Token::String(_) => parse_string(it),
+        Token::True | Token::False | Token::Nil | Token::Char(_) => parse_constant(it),
+        Token::Quote => parse_quote(it),
+        Token::Backquote => parse_quasiquote(it),
+        Token::Comma | Token::CommaAt => parse_unquote(it),
         token => {
             let msg = format!("Unexpected token: {:?}", token);
-            return Err(parse_err(&msg, t.span));
+            Err(parse_err(&msg, t.span))
         }
-    };
-
-    exp
+    }
 }
 
 fn parse_list(it: &mut TokenIterator) -> Result<SExpr, ParseError> {
-    expect(it, Token::OpenParen, "Expected open paren")?;
+    let open = expect(it, Token::OpenParen, "Expected open paren")?;
     let mut items = Vec::new();
     loop {
         skip_whitespace(it);
@@ -49,16 +64,35 @@ fn parse_list(it: &mut TokenIterator) -> Result<SExpr, ParseError> {
         if t.item == Token::CloseParen {
             break;
         }
+        if t.item == Token::Dot {
+            return parse_dotted_tail(it, open, items);
+        }
         let exp = parse_expression(it)?;
         items.push(exp);
     }
-    expect(it, Token::CloseParen, "Expected close paren")?;
-    let spans: Vec<Span> = items.iter().map(|e| e.span).collect();
-    let span = Span::merge(&spans);
-    let exp = Expr::List(items);
+    let close = expect(it, Token::CloseParen, "Expected close paren")?;
+    let span = Span::merge(&[open, close]);
+    let exp = Expr::List(items.into());
     Ok(SExpr::new(exp, span))
 }
 
+/// The rest of `(a b . c)`, after `a b`.
+fn parse_dotted_tail(
+    it: &mut TokenIterator,
+    open: Span,
+    items: Vec<SExpr>,
+) -> Result<SExpr, ParseError> {
+    let dot = expect(it, Token::Dot, "Expected dot")?;
+    if items.is_empty() {
+        return Err(parse_err("Expected an expression before '.'", dot));
+    }
+    let tail = parse_expression(it)?;
+    skip_whitespace(it);
+    let close = expect(it, Token::CloseParen, "Expected close paren after dotted tail")?;
+    let span = Span::merge(&[open, close]);
+    Ok(SExpr::new(Expr::DottedList(items, Box::new(tail)), span))
+}
+
 fn parse_symbol(it: &mut TokenIterator) -> Result<SExpr, ParseError> {
     let t = it.next().unwrap();
     match &t.item {
@@ -72,19 +106,164 @@ fn parse_symbol(it: &mut TokenIterator) -> Result<SExpr, ParseError> {
 
 fn parse_number(it: &mut TokenIterator) -> Result<SExpr, ParseError> {
     let t = it.next().unwrap();
-    match t.item {
+    match &t.item {
         Token::Number(n) => {
-            let exp = Expr::Number(n);
+            let exp = Expr::Number(n.clone());
             Ok(SExpr::new(exp, t.span))
         }
         _ => Err(parse_err("Expected number", t.span)),
     }
 }
 
+fn parse_string(it: &mut TokenIterator) -> Result<SExpr, ParseError> {
+    let t = it.next().unwrap();
+    match &t.item {
+        Token::String(s) => Ok(SExpr::new(Expr::String(s.clone()), t.span)),
+        _ => Err(parse_err("Expected string", t.span)),
+    }
+}
+
+/// `#t`, `#f`, `#n`, the empty list, and characters.
+fn parse_constant(it: &mut TokenIterator) -> Result<SExpr, ParseError> {
+    let t = it.next().unwrap();
+    let exp = match t.item {
+        Token::True => Expr::Bool(true),
+        Token::False => Expr::Bool(false),
+        Token::Nil => Expr::Nil,
+        Token::Char(c) => Expr::Char(c),
+        _ => return Err(parse_err("Expected constant", t.span)),
+    };
+    Ok(SExpr::new(exp, t.span))
+}
+
+/// `'datum` is read as `(quote datum)`.
+fn parse_quote(it: &mut TokenIterator) -> Result<SExpr, ParseError> {
+    let quote = expect(it, Token::Quote, "Expected quote")?;
+    let datum = parse_expression(it)?;
+    let span = Span::merge(&[quote, datum.span]);
+    Ok(SExpr::new(Expr::Quote(Box::new(datum)), span))
+}
+
+/// `` `template `` is read as `(quasiquote template)`.
+fn parse_quasiquote(it: &mut TokenIterator) -> Result<SExpr, ParseError> {
+    let backquote = expect(it, Token::Backquote, "Expected backquote")?;
+    let template = parse_expression(it)?;
+    let span = Span::merge(&[backquote, template.span]);
+    Ok(call("quasiquote", vec![template], span))
+}
+
+/// `,expr` is read as `(unquote expr)` and `,@expr` as
+/// `(unquote-splicing expr)`. They only mean something inside a template.
+fn parse_unquote(it: &mut TokenIterator) -> Result<SExpr, ParseError> {
+    let t = it.next().unwrap();
+    let name = match t.item {
+        Token::Comma => "unquote",
+        _ => "unquote-splicing",
+    };
+    let expr = parse_expression(it)?;
+    let span = Span::merge(&[t.span, expr.span]);
+    let head = SExpr::new(Expr::Symbol(name.to_string()), t.span);
+    Ok(SExpr::new(Expr::List(Rc::new([head, expr])), span))
+}
+
+/// Replaces every `(quasiquote template)` outside of quoted data by the
+/// code that builds the template, e.g. `` `(a ,b ,@c) `` by
+/// `(#%cons 'a (#%cons b (#%append c '())))`.
+fn expand_quasiquotes(expr: &SExpr) -> Result<SExpr, ParseError> {
+    let items = match &expr.item {
+        Expr::List(items) => items,
+        Expr::DottedList(items, tail) => {
+            let items = items.iter().map(expand_quasiquotes).collect::<Result<_, _>>()?;
+            let tail = Box::new(expand_quasiquotes(tail)?);
+            return Ok(SExpr::new(Expr::DottedList(items, tail), expr.span));
+        }
+        _ => return Ok(expr.clone()),
+    };
+    match items.first().map(|head| &head.item) {
+        Some(Expr::Symbol(name)) if name == "quote" => Ok(expr.clone()),
+        Some(Expr::Symbol(name)) if name == "quasiquote" => match special_form(items) {
+            Some((_, template)) => expand_quasiquote(template, 1),
+            None => Err(parse_err("Expected a single template", expr.span)),
+        },
+        _ => {
+            let items = items.iter().map(expand_quasiquotes).collect::<Result<_, _>>()?;
+            Ok(SExpr::new(Expr::List(items), expr.span))
+        }
+    }
+}
+
+/// Rewrites a quasiquote template into list construction. `depth` counts
+/// the enclosing quasiquotes that were not yet cancelled by an unquote.
+fn expand_quasiquote(template: &SExpr, depth: usize) -> Result<SExpr, ParseError> {
+    let span = template.span;
+    let (items, tail) = match &template.item {
+        Expr::List(items) => (&items[..], None),
+        Expr::DottedList(items, tail) => (&items[..], Some(tail)),
+        Expr::Quote(datum) => {
+            let quote = SExpr::new(Expr::Symbol("quote".to_string()), span);
+            let nil = SExpr::new(Expr::Nil, span);
+            let inner = call(CONS, vec![expand_quasiquote(datum, depth)?, nil], span);
+            return Ok(call(CONS, vec![call("quote", vec![quote], span), inner], span));
+        }
+        _ => return Ok(call("quote", vec![template.clone()], span)),
+    };
+    match special_form(items).filter(|_| tail.is_none()) {
+        Some(("unquote", expr)) if depth == 1 => return expand_quasiquotes(expr),
+        Some(("unquote-splicing", _)) if depth == 1 => {
+            return Err(parse_err("',@' is only allowed inside a list", span))
+        }
+        Some((name, expr)) => {
+            let depth = if name == "quasiquote" { depth + 1 } else { depth - 1 };
+            let name = SExpr::new(Expr::Symbol(name.to_string()), span);
+            let nil = SExpr::new(Expr::Nil, span);
+            let inner = call(CONS, vec![expand_quasiquote(expr, depth)?, nil], span);
+            return Ok(call(CONS, vec![call("quote", vec![name], span), inner], span));
+        }
+        None => {}
+    }
+    let mut list = match tail {
+        Some(tail) => expand_quasiquote(tail, depth)?,
+        None => SExpr::new(Expr::Nil, span),
+    };
+    for item in items.iter().rev() {
+        list = match special_form_items(item) {
+            Some(("unquote-splicing", expr)) if depth == 1 => {
+                call(APPEND, vec![expand_quasiquotes(expr)?, list], item.span)
+            }
+            _ => call(CONS, vec![expand_quasiquote(item, depth)?, list], item.span),
+        };
+    }
+    Ok(list)
+}
+
+/// Matches `(name expr)` for the forms that affect quasiquote expansion.
+fn special_form(items: &[SExpr]) -> Option<(&str, &SExpr)> {
+    match items {
+        [SExpr { item: Expr::Symbol(name), .. }, expr] => match name.as_str() {
+            "quasiquote" | "unquote" | "unquote-splicing" => Some((name, expr)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn special_form_items(expr: &SExpr) -> Option<(&str, &SExpr)> {
+    match &expr.item {
+        Expr::List(items) => special_form(items),
+        _ => None,
+    }
+}
+
+fn call(name: &str, mut args: Vec<SExpr>, span: Span) -> SExpr {
+    args.insert(0, SExpr::new(Expr::Symbol(name.to_string()), span));
+    SExpr::new(Expr::List(args.into()), span)
+}
+
+/// Skips whitespace and comments.
 fn skip_whitespace(it: &mut TokenIterator) {
     while let Some(t) = it.peek() {
         match t.item {
-            Token::Whitespace(_) => {
+            Token::Whitespace(_) | Token::Comment(_) => {
                 it.next();
             }
             _ => break,
@@ -92,23 +271,11 @@ fn skip_whitespace(it: &mut TokenIterator) {
     }
 }
 
-fn expect_identifier(it: &mut TokenIterator) -> Result<String, ParseError> {
-    match it.next() {
-        None => Err(parse_err(
-            "expected identifier, got end of input",
-            Span::new(0, 0),
-        )),
-        Some(t) => match &t.item {
-            Token::Symbol(s) => Ok(s.clone()),
-            _ => Err(parse_err("Expected identifier", t.span)),
-        },
-    }
-}
-
-fn expect(it: &mut TokenIterator, expected: Token, msg: &str) -> Result<(), ParseError> {
+fn expect(it: &mut TokenIterator, expected: Token, msg: &str) -> Result<Span, ParseError> {
     let t = it.next().ok_or(parse_err(msg, Span::new(0, 0)))?;
     if t.item != expected {
         return Err(parse_err(msg, t.span));
     }
-    Ok(())
+    Ok(t.span)
 }
+
@@ -0,0 +1,56 @@
+/// Definitions loaded into every Lisp session. The derived forms are macros
+/// over `lambda` and `if`.
+pub(super) const SOURCE: &str = r#"
+(define (list . items) items)
+(define (cadr x) (car (cdr x)))
+(define (not x) (if x #f #t))
+(define (map f items)
+  (if (null? items) '() (cons (f (car items)) (map f (cdr items)))))
+
+; (let ((name value) ...) body...)
+(defmacro let (bindings . body)
+  `((lambda ,(map car bindings) ,@body) ,@(map cadr bindings)))
+
+; (let* ((name value) ...) body...) binds one name after another, so each
+; value can refer to the names before it.
+(defmacro let* (bindings . body)
+  (if (null? bindings) `(let () ,@body)
+    `(let (,(car bindings)) (let* ,(cdr bindings) ,@body))))
+
+; (letrec ((name value) ...) body...) binds the names before evaluating any
+; value, so the values, usually lambdas, can refer to each other. Until its
+; value is assigned, a name is bound to #f.
+(defmacro letrec (bindings . body)
+  `(let ,(map (lambda (b) (list (car b) #f)) bindings)
+     ,@(map (lambda (b) `(set! ,@b)) bindings)
+     (let () ,@body)))
+
+(defmacro when (test . body)
+  `(if ,test (begin ,@body)))
+
+(defmacro unless (test . body)
+  `(if ,test (if #f #f) (begin ,@body)))
+
+(defmacro and args
+  (if (null? args) #t
+    (if (null? (cdr args)) (car args)
+      `(if ,(car args) (and ,@(cdr args)) #f))))
+
+; The value of the first argument is bound to a fresh symbol, so that it is
+; evaluated only once without capturing a variable of the caller.
+(defmacro or args
+  (if (null? args) #f
+    (if (null? (cdr args)) (car args)
+      (let ((t (gensym)))
+        `(let ((,t ,(car args))) (if ,t ,t (or ,@(cdr args))))))))
+
+; (cond (test body...) ... (else body...))
+(defmacro cond clauses
+  (if (null? clauses) '(if #f #f)
+    (let ((clause (car clauses)))
+      (if (eq? (car clause) 'else)
+        `(begin ,@(cdr clause))
+        `(if ,(car clause)
+           (begin ,@(cdr clause))
+           (cond ,@(cdr clauses)))))))
+"#;
@@ -0,0 +1,783 @@
+use super::interpreter::arguments;
+use super::interpreter::Interpreter;
+use super::heap::Handle;
+use super::heap::Heap;
+use super::heap::Object;
+use super::number::Number;
+use super::parser;
+use super::value::Control;
+use super::value::Env;
+use super::value::HashTable;
+use super::value::Key;
+use super::value::Primitive;
+use super::value::PrimitiveFn;
+use super::value::Value;
+
+use crate::parsing_utils::runtime_err;
+use crate::parsing_utils::RuntimeError;
+use crate::parsing_utils::Span;
+use crate::parsing_utils::Spanned;
+
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+type Args<'a> = &'a [Spanned<Value>];
+
+const PRIMITIVES: [(&str, PrimitiveFn); 61] = [
+    ("+", add),
+    ("-", sub),
+    ("*", mul),
+    ("/", div),
+    ("quotient", quotient),
+    ("remainder", remainder),
+    ("<", less_than),
+    (">", greater_than),
+    ("<=", less_or_equal),
+    (">=", greater_or_equal),
+    ("=", num_eq),
+    ("exact", exact),
+    ("inexact", inexact),
+    ("exact?", is_exact),
+    ("integer?", is_integer),
+    ("print", print),
+    ("cons", cons),
+    ("car", car),
+    ("cdr", cdr),
+    ("set-car!", set_car),
+    ("set-cdr!", set_cdr),
+    ("append", append),
+    ("null?", is_null),
+    ("number?", is_number),
+    ("list?", is_list),
+    ("eq?", is_eq),
+    ("string?", is_string),
+    ("char?", is_char),
+    ("symbol?", is_symbol),
+    ("string-length", string_length),
+    ("string-ref", string_ref),
+    ("substring", substring),
+    ("string-append", string_append),
+    ("string=?", string_eq),
+    ("string->symbol", string_to_symbol),
+    ("symbol->string", symbol_to_string),
+    ("number->string", number_to_string),
+    ("string->number", string_to_number),
+    ("string->list", string_to_list),
+    ("list->string", list_to_string),
+    ("char->integer", char_to_integer),
+    ("integer->char", integer_to_char),
+    ("vector", vector_),
+    ("make-vector", make_vector),
+    ("vector?", is_vector),
+    ("vector-length", vector_length),
+    ("vector-ref", vector_ref),
+    ("vector-set!", vector_set),
+    ("vector->list", vector_to_list),
+    ("list->vector", list_to_vector),
+    ("make-hash-table", make_hash_table),
+    ("hash-table?", is_hash_table),
+    ("hash-table-set!", hash_table_set),
+    ("hash-table-ref", hash_table_ref),
+    ("hash-table-contains?", hash_table_contains),
+    ("hash-table-delete!", hash_table_delete),
+    ("hash-table-count", hash_table_count),
+    ("hash-table-keys", hash_table_keys),
+    ("gensym", gensym),
+    ("load", load),
+    ("gc-stats", gc_stats),
+];
+
+const CONTROLS: [(&str, Control); 3] = [
+    ("call-with-current-continuation", Control::CallCC),
+    ("call/cc", Control::CallCC),
+    ("dynamic-wind", Control::DynamicWind),
+];
+
+/// Binds every primitive in `env`.
+pub(super) fn install(env: &mut Env) {
+    for (name, func) in PRIMITIVES {
+        env.define(name, Value::Primitive(Primitive { name, func }));
+    }
+    for (name, control) in CONTROLS {
+        env.define(name, Value::Control(control));
+    }
+    env.define(parser::CONS, Value::Primitive(Primitive { name: "cons", func: cons }));
+    env.define(parser::APPEND, Value::Primitive(Primitive { name: "append", func: append }));
+}
+
+fn expect_arity(args: Args, n: usize, span: Span) -> Result<(), RuntimeError> {
+    if args.len() != n {
+        let msg = format!("Expected {}, got {}", arguments(n), args.len());
+        return Err(runtime_err(&msg, span));
+    }
+    Ok(())
+}
+
+fn expect_at_least(args: Args, n: usize, span: Span) -> Result<(), RuntimeError> {
+    if args.len() < n {
+        let msg = format!("Expected at least {}, got {}", arguments(n), args.len());
+        return Err(runtime_err(&msg, span));
+    }
+    Ok(())
+}
+
+fn type_error(heap: &Heap, expected: &str, arg: &Spanned<Value>) -> RuntimeError {
+    let v = &arg.item;
+    let article = if expected.starts_with(['a', 'e', 'i', 'o', 'u']) { "an" } else { "a" };
+    let msg = format!(
+        "Expected {} {}, got {} {}",
+        article,
+        expected,
+        v.type_name(),
+        heap.show(v)
+    );
+    runtime_err(&msg, arg.span)
+}
+
+fn number<'a>(heap: &Heap, arg: &'a Spanned<Value>) -> Result<&'a Number, RuntimeError> {
+    match &arg.item {
+        Value::Number(n) => Ok(n),
+        _ => Err(type_error(heap, "number", arg)),
+    }
+}
+
+// Arithmetic
+
+fn add(interpreter: &mut Interpreter, args: Args, _: Span) -> Result<Value, RuntimeError> {
+    let heap = &interpreter.heap;
+    let mut sum = Number::Int(0);
+    for arg in args {
+        sum = &sum + number(heap, arg)?;
+    }
+    Ok(Value::Number(sum))
+}
+
+fn mul(interpreter: &mut Interpreter, args: Args, _: Span) -> Result<Value, RuntimeError> {
+    let heap = &interpreter.heap;
+    let mut product = Number::Int(1);
+    for arg in args {
+        product = &product * number(heap, arg)?;
+    }
+    Ok(Value::Number(product))
+}
+
+/// `(- x)` negates `x`, `(- x y z)` is `x - y - z`.
+fn sub(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    let heap = &interpreter.heap;
+    expect_at_least(args, 1, span)?;
+    let first = number(heap, &args[0])?;
+    if args.len() == 1 {
+        return Ok(Value::Number(-first));
+    }
+    let mut difference = first.clone();
+    for arg in &args[1..] {
+        difference = &difference - number(heap, arg)?;
+    }
+    Ok(Value::Number(difference))
+}
+
+/// Exact division yields a fraction unless the divisor divides the
+/// dividend. `(/ x)` is `1 / x`.
+fn div(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    let heap = &interpreter.heap;
+    expect_at_least(args, 1, span)?;
+    let (mut quotient, divisors) = match args.len() {
+        1 => (Number::Int(1), args),
+        _ => (number(heap, &args[0])?.clone(), &args[1..]),
+    };
+    for arg in divisors {
+        quotient = quotient
+            .checked_div(number(heap, arg)?)
+            .ok_or_else(|| runtime_err("Division by zero", arg.span))?;
+    }
+    Ok(Value::Number(quotient))
+}
+
+/// The quotient or remainder of two integers, the quotient rounded towards
+/// zero.
+fn integer_division(
+    heap: &Heap,
+    args: Args,
+    span: Span,
+    remainder: bool,
+) -> Result<Value, RuntimeError> {
+    expect_arity(args, 2, span)?;
+    let (a, b) = (number(heap, &args[0])?, number(heap, &args[1])?);
+    for arg in args {
+        if !number(heap, arg)?.is_integer() {
+            return Err(type_error(heap, "integer", arg));
+        }
+    }
+    let (q, r) = a
+        .quotient_remainder(b)
+        .ok_or_else(|| runtime_err("Division by zero", args[1].span))?;
+    Ok(Value::Number(if remainder { r } else { q }))
+}
+
+fn quotient(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    integer_division(&interpreter.heap, args, span, false)
+}
+
+fn remainder(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    integer_division(&interpreter.heap, args, span, true)
+}
+
+/// Checks that `holds` is true for every two consecutive arguments. Nothing
+/// holds for a float that is not a number.
+fn compare(
+    heap: &Heap,
+    args: Args,
+    span: Span,
+    holds: fn(Ordering) -> bool,
+) -> Result<Value, RuntimeError> {
+    expect_at_least(args, 1, span)?;
+    let numbers = args.iter().map(|arg| number(heap, arg)).collect::<Result<Vec<_>, _>>()?;
+    let all = numbers.windows(2).all(|w| w[0].compare(w[1]).is_some_and(holds));
+    Ok(Value::Bool(all))
+}
+
+fn less_than(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    compare(&interpreter.heap, args, span, Ordering::is_lt)
+}
+
+fn greater_than(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    compare(&interpreter.heap, args, span, Ordering::is_gt)
+}
+
+fn less_or_equal(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    compare(&interpreter.heap, args, span, Ordering::is_le)
+}
+
+fn greater_or_equal(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    compare(&interpreter.heap, args, span, Ordering::is_ge)
+}
+
+fn num_eq(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    compare(&interpreter.heap, args, span, Ordering::is_eq)
+}
+
+/// The exact number equal to a float.
+fn exact(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    let heap = &interpreter.heap;
+    expect_arity(args, 1, span)?;
+    let n = number(heap, &args[0])?.to_exact().ok_or_else(|| {
+        let msg = format!("{} has no exact equivalent", heap.show(&args[0].item));
+        runtime_err(&msg, args[0].span)
+    })?;
+    Ok(Value::Number(n))
+}
+
+/// The float closest to a number.
+fn inexact(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    let heap = &interpreter.heap;
+    expect_arity(args, 1, span)?;
+    Ok(Value::Number(number(heap, &args[0])?.to_inexact()))
+}
+
+fn is_exact(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    let heap = &interpreter.heap;
+    expect_arity(args, 1, span)?;
+    Ok(Value::Bool(number(heap, &args[0])?.is_exact()))
+}
+
+fn is_integer(_: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    expect_arity(args, 1, span)?;
+    let integer = matches!(&args[0].item, Value::Number(n) if n.is_integer());
+    Ok(Value::Bool(integer))
+}
+
+// Output
+
+/// Writes its arguments, separated by spaces, on a line of their own.
+fn print(interpreter: &mut Interpreter, args: Args, _: Span) -> Result<Value, RuntimeError> {
+    let heap = &interpreter.heap;
+    let line: Vec<String> = args.iter().map(|a| heap.to_display_string(&a.item)).collect();
+    interpreter.output.push_str(&line.join(" "));
+    interpreter.output.push('\n');
+    Ok(Value::Void)
+}
+
+// Pairs and lists
+
+fn cons(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    expect_arity(args, 2, span)?;
+    let heap = &mut interpreter.heap;
+    Ok(heap.cons(args[0].item.clone(), args[1].item.clone()))
+}
+
+fn car(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    let heap = &interpreter.heap;
+    expect_arity(args, 1, span)?;
+    match &args[0].item {
+        Value::Pair(pair) => Ok(heap.car(*pair)),
+        _ => Err(type_error(heap, "pair", &args[0])),
+    }
+}
+
+fn cdr(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    let heap = &interpreter.heap;
+    expect_arity(args, 1, span)?;
+    match &args[0].item {
+        Value::Pair(pair) => Ok(heap.cdr(*pair)),
+        _ => Err(type_error(heap, "pair", &args[0])),
+    }
+}
+
+fn pair(heap: &Heap, arg: &Spanned<Value>) -> Result<Handle, RuntimeError> {
+    match &arg.item {
+        Value::Pair(pair) => Ok(*pair),
+        _ => Err(type_error(heap, "pair", arg)),
+    }
+}
+
+fn set_car(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    expect_arity(args, 2, span)?;
+    let pair = pair(&interpreter.heap, &args[0])?;
+    interpreter.heap.set_car(pair, args[1].item.clone());
+    Ok(Value::Void)
+}
+
+fn set_cdr(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    expect_arity(args, 2, span)?;
+    let pair = pair(&interpreter.heap, &args[0])?;
+    interpreter.heap.set_cdr(pair, args[1].item.clone());
+    Ok(Value::Void)
+}
+
+/// Concatenates lists. The last argument is shared rather than copied and
+/// may be any value.
+fn append(interpreter: &mut Interpreter, args: Args, _: Span) -> Result<Value, RuntimeError> {
+    let heap = &mut interpreter.heap;
+    let (last, lists) = match args.split_last() {
+        Some(split) => split,
+        None => return Ok(Value::Nil),
+    };
+    let mut result = last.item.clone();
+    for arg in lists.iter().rev() {
+        let items = heap.to_vec(&arg.item).ok_or_else(|| type_error(heap, "list", arg))?;
+        for item in items.into_iter().rev() {
+            result = heap.cons(item, result);
+        }
+    }
+    Ok(result)
+}
+
+fn is_null(_: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    expect_arity(args, 1, span)?;
+    Ok(Value::Bool(matches!(args[0].item, Value::Nil)))
+}
+
+fn is_number(_: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    expect_arity(args, 1, span)?;
+    Ok(Value::Bool(matches!(args[0].item, Value::Number(_))))
+}
+
+/// True for proper lists, including the empty list.
+fn is_list(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    expect_arity(args, 1, span)?;
+    Ok(Value::Bool(interpreter.heap.to_vec(&args[0].item).is_some()))
+}
+
+/// Atoms are compared by value; pairs, vectors, hash tables and procedures
+/// by identity.
+fn is_eq(_: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    expect_arity(args, 2, span)?;
+    let eq = match (&args[0].item, &args[1].item) {
+        (Value::Nil, Value::Nil) => true,
+        (Value::Void, Value::Void) => true,
+        (Value::Bool(a), Value::Bool(b)) => a == b,
+        (Value::Number(a), Value::Number(b)) => a == b,
+        (Value::Symbol(a), Value::Symbol(b)) => a == b,
+        (Value::String(a), Value::String(b)) => a == b,
+        (Value::Char(a), Value::Char(b)) => a == b,
+        (Value::Pair(a), Value::Pair(b)) => a == b,
+        (Value::Vector(a), Value::Vector(b)) => a == b,
+        (Value::HashTable(a), Value::HashTable(b)) => a == b,
+        (Value::Closure(a), Value::Closure(b)) => a == b,
+        (Value::Primitive(a), Value::Primitive(b)) => a.name == b.name,
+        (Value::Control(a), Value::Control(b)) => a == b,
+        (Value::Continuation(a), Value::Continuation(b)) => Rc::ptr_eq(a, b),
+        _ => false,
+    };
+    Ok(Value::Bool(eq))
+}
+
+// Strings and characters
+
+fn string<'a>(heap: &Heap, arg: &'a Spanned<Value>) -> Result<&'a str, RuntimeError> {
+    match &arg.item {
+        Value::String(s) => Ok(s),
+        _ => Err(type_error(heap, "string", arg)),
+    }
+}
+
+fn character(heap: &Heap, arg: &Spanned<Value>) -> Result<char, RuntimeError> {
+    match &arg.item {
+        Value::Char(c) => Ok(*c),
+        _ => Err(type_error(heap, "character", arg)),
+    }
+}
+
+/// An exact, non-negative integer, to count or index with.
+fn natural(heap: &Heap, arg: &Spanned<Value>) -> Result<usize, RuntimeError> {
+    match &arg.item {
+        Value::Number(Number::Int(i)) if *i >= 0 => Ok(*i as usize),
+        _ => Err(type_error(heap, "index", arg)),
+    }
+}
+
+/// An index of one of `length` elements. `end` allows `length` itself, for
+/// the end of a range.
+fn index(heap: &Heap, arg: &Spanned<Value>, length: usize, end: bool) -> Result<usize, RuntimeError> {
+    let i = natural(heap, arg)?;
+    if i > length || (i == length && !end) {
+        let msg = format!("Index {} is out of range for length {}", i, length);
+        return Err(runtime_err(&msg, arg.span));
+    }
+    Ok(i)
+}
+
+fn is_string(_: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    expect_arity(args, 1, span)?;
+    Ok(Value::Bool(matches!(args[0].item, Value::String(_))))
+}
+
+fn is_char(_: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    expect_arity(args, 1, span)?;
+    Ok(Value::Bool(matches!(args[0].item, Value::Char(_))))
+}
+
+fn is_symbol(_: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    expect_arity(args, 1, span)?;
+    Ok(Value::Bool(matches!(args[0].item, Value::Symbol(_))))
+}
+
+/// The number of characters in a string.
+fn string_length(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    let heap = &interpreter.heap;
+    expect_arity(args, 1, span)?;
+    let length = string(heap, &args[0])?.chars().count();
+    Ok(Value::Number(Number::Int(length as i64)))
+}
+
+fn string_ref(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    let heap = &interpreter.heap;
+    expect_arity(args, 2, span)?;
+    let s = string(heap, &args[0])?;
+    let i = index(heap, &args[1], s.chars().count(), false)?;
+    Ok(Value::Char(s.chars().nth(i).unwrap()))
+}
+
+/// `(substring s start end)` is the characters of `s` from `start` up to,
+/// but not including, `end`, which defaults to the end of `s`.
+fn substring(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    let heap = &interpreter.heap;
+    if args.len() != 3 {
+        expect_arity(args, 2, span)?;
+    }
+    let s = string(heap, &args[0])?;
+    let length = s.chars().count();
+    let start = index(heap, &args[1], length, true)?;
+    let end = match args.get(2) {
+        Some(arg) => index(heap, arg, length, true)?,
+        None => length,
+    };
+    if end < start {
+        let msg = format!("The end {} is before the start {}", end, start);
+        return Err(runtime_err(&msg, args[2].span));
+    }
+    Ok(Value::String(s.chars().skip(start).take(end - start).collect()))
+}
+
+fn string_append(interpreter: &mut Interpreter, args: Args, _: Span) -> Result<Value, RuntimeError> {
+    let heap = &interpreter.heap;
+    let mut result = String::new();
+    for arg in args {
+        result.push_str(string(heap, arg)?);
+    }
+    Ok(Value::String(result))
+}
+
+fn string_eq(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    let heap = &interpreter.heap;
+    expect_at_least(args, 1, span)?;
+    let strings = args.iter().map(|arg| string(heap, arg)).collect::<Result<Vec<_>, _>>()?;
+    Ok(Value::Bool(strings.windows(2).all(|w| w[0] == w[1])))
+}
+
+fn string_to_symbol(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    let heap = &interpreter.heap;
+    expect_arity(args, 1, span)?;
+    Ok(Value::Symbol(string(heap, &args[0])?.to_string()))
+}
+
+fn symbol_to_string(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    let heap = &interpreter.heap;
+    expect_arity(args, 1, span)?;
+    match &args[0].item {
+        Value::Symbol(name) => Ok(Value::String(name.clone())),
+        _ => Err(type_error(heap, "symbol", &args[0])),
+    }
+}
+
+fn number_to_string(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    let heap = &interpreter.heap;
+    expect_arity(args, 1, span)?;
+    Ok(Value::String(number(heap, &args[0])?.to_string()))
+}
+
+/// The number a string reads as, or `#f` if it is not one.
+fn string_to_number(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    let heap = &interpreter.heap;
+    expect_arity(args, 1, span)?;
+    match Number::parse(string(heap, &args[0])?) {
+        Some(n) => Ok(Value::Number(n)),
+        None => Ok(Value::Bool(false)),
+    }
+}
+
+fn string_to_list(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    let heap = &mut interpreter.heap;
+    expect_arity(args, 1, span)?;
+    let chars = string(heap, &args[0])?.chars().map(Value::Char).collect();
+    Ok(heap.list(chars))
+}
+
+fn list_to_string(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    let heap = &interpreter.heap;
+    expect_arity(args, 1, span)?;
+    let items = heap.to_vec(&args[0].item).ok_or_else(|| type_error(heap, "list", &args[0]))?;
+    let mut s = String::new();
+    for item in items {
+        match item {
+            Value::Char(c) => s.push(c),
+            _ => return Err(type_error(heap, "list of characters", &args[0])),
+        }
+    }
+    Ok(Value::String(s))
+}
+
+/// The Unicode code point of a character.
+fn char_to_integer(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    let heap = &interpreter.heap;
+    expect_arity(args, 1, span)?;
+    let code = character(heap, &args[0])? as i64;
+    Ok(Value::Number(Number::Int(code)))
+}
+
+fn integer_to_char(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    let heap = &interpreter.heap;
+    expect_arity(args, 1, span)?;
+    let code = natural(heap, &args[0])?;
+    let c = u32::try_from(code).ok().and_then(char::from_u32).ok_or_else(|| {
+        let msg = format!("No character has the code {}", code);
+        runtime_err(&msg, args[0].span)
+    })?;
+    Ok(Value::Char(c))
+}
+
+// Vectors
+
+fn vector(heap: &Heap, arg: &Spanned<Value>) -> Result<Handle, RuntimeError> {
+    match &arg.item {
+        Value::Vector(items) => Ok(*items),
+        _ => Err(type_error(heap, "vector", arg)),
+    }
+}
+
+fn new_vector(heap: &mut Heap, items: Vec<Value>) -> Value {
+    Value::Vector(heap.alloc(Object::Vector(items)))
+}
+
+fn vector_(interpreter: &mut Interpreter, args: Args, _: Span) -> Result<Value, RuntimeError> {
+    let items = args.iter().map(|a| a.item.clone()).collect();
+    Ok(new_vector(&mut interpreter.heap, items))
+}
+
+/// `(make-vector n fill)` is a vector of `n` elements, all `fill`, which
+/// defaults to `#f`.
+fn make_vector(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    if args.len() != 2 {
+        expect_arity(args, 1, span)?;
+    }
+    let length = natural(&interpreter.heap, &args[0])?;
+    let fill = args.get(1).map_or(Value::Bool(false), |a| a.item.clone());
+    let mut items = Vec::new();
+    if items.try_reserve_exact(length).is_err() {
+        let msg = format!("Cannot allocate a vector of length {}", length);
+        return Err(runtime_err(&msg, args[0].span));
+    }
+    items.resize(length, fill);
+    Ok(new_vector(&mut interpreter.heap, items))
+}
+
+fn is_vector(_: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    expect_arity(args, 1, span)?;
+    Ok(Value::Bool(matches!(args[0].item, Value::Vector(_))))
+}
+
+fn vector_length(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    expect_arity(args, 1, span)?;
+    let heap = &interpreter.heap;
+    let length = heap.vector(vector(heap, &args[0])?).len();
+    Ok(Value::Number(Number::Int(length as i64)))
+}
+
+fn vector_ref(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    expect_arity(args, 2, span)?;
+    let heap = &interpreter.heap;
+    let items = heap.vector(vector(heap, &args[0])?);
+    let i = index(heap, &args[1], items.len(), false)?;
+    Ok(items[i].clone())
+}
+
+fn vector_set(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    expect_arity(args, 3, span)?;
+    let heap = &mut interpreter.heap;
+    let v = vector(heap, &args[0])?;
+    let i = index(heap, &args[1], heap.vector(v).len(), false)?;
+    heap.vector_mut(v)[i] = args[2].item.clone();
+    Ok(Value::Void)
+}
+
+fn vector_to_list(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    expect_arity(args, 1, span)?;
+    let heap = &mut interpreter.heap;
+    let items = heap.vector(vector(heap, &args[0])?).clone();
+    Ok(heap.list(items))
+}
+
+fn list_to_vector(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    expect_arity(args, 1, span)?;
+    let heap = &mut interpreter.heap;
+    let items = heap.to_vec(&args[0].item).ok_or_else(|| type_error(heap, "list", &args[0]))?;
+    Ok(new_vector(heap, items))
+}
+
+// Hash tables
+
+fn hash_table(heap: &Heap, arg: &Spanned<Value>) -> Result<Handle, RuntimeError> {
+    match &arg.item {
+        Value::HashTable(table) => Ok(*table),
+        _ => Err(type_error(heap, "hash table", arg)),
+    }
+}
+
+fn key(heap: &Heap, arg: &Spanned<Value>) -> Result<Key, RuntimeError> {
+    arg.item.to_key().ok_or_else(|| type_error(heap, "hash table key", arg))
+}
+
+fn make_hash_table(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    expect_arity(args, 0, span)?;
+    let table = interpreter.heap.alloc(Object::HashTable(HashTable::default()));
+    Ok(Value::HashTable(table))
+}
+
+fn is_hash_table(_: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    expect_arity(args, 1, span)?;
+    Ok(Value::Bool(matches!(args[0].item, Value::HashTable(_))))
+}
+
+fn hash_table_set(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    expect_arity(args, 3, span)?;
+    let heap = &mut interpreter.heap;
+    let table = hash_table(heap, &args[0])?;
+    let key = key(heap, &args[1])?;
+    let entry = (args[1].item.clone(), args[2].item.clone());
+    heap.hash_table_mut(table).entries.insert(key, entry);
+    Ok(Value::Void)
+}
+
+/// `(hash-table-ref table key default)` is the value of `key`, or `default`
+/// if there is none. Without a default, a missing key is an error.
+fn hash_table_ref(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    if args.len() != 3 {
+        expect_arity(args, 2, span)?;
+    }
+    let heap = &interpreter.heap;
+    let table = heap.hash_table(hash_table(heap, &args[0])?);
+    match (table.entries.get(&key(heap, &args[1])?), args.get(2)) {
+        (Some((_, value)), _) => Ok(value.clone()),
+        (None, Some(default)) => Ok(default.item.clone()),
+        (None, None) => {
+            let msg = format!("No value for the key {}", heap.show(&args[1].item));
+            Err(runtime_err(&msg, args[1].span))
+        }
+    }
+}
+
+fn hash_table_contains(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    expect_arity(args, 2, span)?;
+    let heap = &interpreter.heap;
+    let table = heap.hash_table(hash_table(heap, &args[0])?);
+    Ok(Value::Bool(table.entries.contains_key(&key(heap, &args[1])?)))
+}
+
+fn hash_table_delete(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    expect_arity(args, 2, span)?;
+    let heap = &mut interpreter.heap;
+    let table = hash_table(heap, &args[0])?;
+    let key = key(heap, &args[1])?;
+    heap.hash_table_mut(table).entries.remove(&key);
+    Ok(Value::Void)
+}
+
+fn hash_table_count(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    expect_arity(args, 1, span)?;
+    let heap = &interpreter.heap;
+    let count = heap.hash_table(hash_table(heap, &args[0])?).entries.len();
+    Ok(Value::Number(Number::Int(count as i64)))
+}
+
+/// The keys of a table, in no particular order.
+fn hash_table_keys(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    expect_arity(args, 1, span)?;
+    let heap = &mut interpreter.heap;
+    let table = heap.hash_table(hash_table(heap, &args[0])?);
+    let keys = table.entries.values().map(|(k, _)| k.clone()).collect();
+    Ok(heap.list(keys))
+}
+
+// Macros
+
+/// A fresh symbol for macro expansions to bind. The reader cannot produce
+/// it, so it cannot capture a variable of the code the macro is used in.
+fn gensym(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    expect_arity(args, 0, span)?;
+    interpreter.gensyms += 1;
+    Ok(Value::Symbol(format!("#:g{}", interpreter.gensyms)))
+}
+
+// Files
+
+/// Evaluates the forms in a file, as if they were entered in the REPL, and
+/// returns the value of the last one.
+fn load(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    expect_arity(args, 1, span)?;
+    let path = match &args[0].item {
+        Value::String(path) => path,
+        _ => return Err(type_error(&interpreter.heap, "string", &args[0])),
+    };
+    let source = std::fs::read_to_string(path).map_err(|e| {
+        runtime_err(&format!("Cannot read {}: {}", path, e), args[0].span)
+    })?;
+    interpreter
+        .eval_source(&source)
+        .map_err(|e| runtime_err(&format!("In {}:\n{}", path, e), args[0].span))
+}
+
+// Memory
+
+/// Collects garbage, then returns an association list of the objects left
+/// on the heap, the collections so far and the objects they freed.
+fn gc_stats(interpreter: &mut Interpreter, args: Args, span: Span) -> Result<Value, RuntimeError> {
+    expect_arity(args, 0, span)?;
+    interpreter.collect_garbage();
+    let heap = &mut interpreter.heap;
+    let stats = [("live", heap.len()), ("collections", heap.collections), ("freed", heap.freed)];
+    let entries = stats
+        .into_iter()
+        .map(|(name, n)| heap.cons(Value::Symbol(name.to_string()), Value::Number(Number::Int(n as i64))))
+        .collect();
+    Ok(heap.list(entries))
+}
@@ -1,4 +1,5 @@
-use crate::parsing_utils::tok_err;
+use super::number::Number;
+
 use crate::parsing_utils::InputIterator;
 use crate::parsing_utils::Spanned;
 use crate::parsing_utils::TokenizeError;
@@ -9,23 +10,19 @@ pub(super) type SToken = Spanned<Token>;
 pub(super) enum Token {
     OpenParen,
     CloseParen,
+    Dot,
     Symbol(String),
-    Number(i64),
+    Number(Number),
     String(String),
+    Char(char),
     Quote,
     Backquote,
     Comma,
+    CommaAt,
     True,
     False,
     Nil,
     Comment(String),
-    Plus,
-    Minus,
-    Multiply,
-    Divide,
-    Equal,
-    LessThan,
-    GreaterThan,
     Whitespace(String),
 }
 
@@ -37,28 +34,25 @@ pub(super) fn tokenize(input: &str) -> Result<Vec<SToken>, TokenizeError> {
             ')' => it.next_and_push(Token::CloseParen, 1),
             '\'' => it.next_and_push(Token::Quote, 1),
             '`' => it.next_and_push(Token::Backquote, 1),
-            ',' => it.next_and_push(Token::Comma, 1),
-            '+' => it.next_and_push(Token::Plus, 1),
-            '-' => it.next_and_push(Token::Minus, 1),
-            '*' => it.next_and_push(Token::Multiply, 1),
-            '/' => it.next_and_push(Token::Divide, 1),
-            '=' => it.next_and_push(Token::Equal, 1),
-            '<' => it.next_and_push(Token::LessThan, 1),
-            '>' => it.next_and_push(Token::GreaterThan, 1),
+            ',' => tokenize_comma(&mut it),
             '#' => tokenize_constants(&mut it)?,
             ';' => tokenize_comment(&mut it),
             '"' => tokenize_string(&mut it)?,
-            c if c.is_digit(10) => tokenize_number(&mut it)?,
-            c if c.is_alphabetic() => tokenize_symbol(&mut it),
             c if c.is_whitespace() => tokenize_whitespace(&mut it),
-            c => {
-                let msg = format!("Invalid character: {}", c);
-                it.next();
-                return Err(it.tok_err(&msg, 1));
-            }
+            _ => tokenize_atom(&mut it)?,
         }
     }
-    return Ok(it.tokens);
+    Ok(it.tokens)
+}
+
+/// `,` or `,@`.
+fn tokenize_comma(it: &mut InputIterator<Token>) {
+    it.next();
+    if it.peek() == Some(&'@') {
+        it.next_and_push(Token::CommaAt, 2);
+    } else {
+        it.push(Token::Comma, 1);
+    }
 }
 
 fn tokenize_constants(it: &mut InputIterator<Token>) -> Result<(), TokenizeError> {
@@ -67,6 +61,7 @@ fn tokenize_constants(it: &mut InputIterator<Token>) -> Result<(), TokenizeError
         Some('t') => it.next_and_push(Token::True, 2),
         Some('f') => it.next_and_push(Token::False, 2),
         Some('n') => it.next_and_push(Token::Nil, 2),
+        Some('\\') => return tokenize_char(it),
         Some(c) => {
             let msg = format!("Invalid character after #: #{}", c);
             it.next();
@@ -77,39 +72,73 @@ fn tokenize_constants(it: &mut InputIterator<Token>) -> Result<(), TokenizeError
     Ok(())
 }
 
-fn tokenize_string(it: &mut InputIterator<Token>) -> Result<(), TokenizeError> {
-    let mut s = String::new();
+/// `#\a`, or a character by name, like `#\space`. The character right after
+/// `#\` is taken even if it is a delimiter, so `#\(` is a character.
+fn tokenize_char(it: &mut InputIterator<Token>) -> Result<(), TokenizeError> {
     it.next();
+    let mut name = String::new();
+    if let Some(&c) = it.peek() {
+        name.push(c);
+        it.next();
+    }
     while let Some(&c) = it.peek() {
-        match c {
-            '"' => {
-                it.next();
-                let len = s.len();
-                it.push(Token::String(s), len + 2);
-                return Ok(());
-            }
-            _ => {
-                s.push(c);
-                it.next();
-            }
+        if is_delimiter(c) {
+            break;
         }
+        name.push(c);
+        it.next();
     }
-    Err(it.tok_err("Unterminated string", s.len()))
+    let len = name.chars().count() + 2;
+    let mut chars = name.chars();
+    let c = match (chars.next(), chars.next()) {
+        (Some(c), None) => c,
+        _ => match name.as_str() {
+            "space" => ' ',
+            "newline" => '\n',
+            "tab" => '\t',
+            "" => return Err(it.tok_err("Unexpected end of input", len)),
+            _ => return Err(it.tok_err(&format!("Unknown character: #\\{}", name), len)),
+        },
+    };
+    it.push(Token::Char(c), len);
+    Ok(())
 }
 
-fn tokenize_symbol(it: &mut InputIterator<Token>) {
-    let mut symbol = String::new();
+/// A string between double quotes, in which `\"`, `\\`, `\n` and `\t` are
+/// escapes.
+fn tokenize_string(it: &mut InputIterator<Token>) -> Result<(), TokenizeError> {
+    let mut s = String::new();
+    let mut len = 1;
+    it.next();
     while let Some(&c) = it.peek() {
+        it.next();
+        len += 1;
         match c {
-            c if c.is_alphabetic() || c.is_digit(10) || c == '_' => {
-                symbol.push(c);
+            '"' => {
+                it.push(Token::String(s), len);
+                return Ok(());
+            }
+            '\\' => {
+                let escaped = match it.peek() {
+                    Some('"') => '"',
+                    Some('\\') => '\\',
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some(&c) => {
+                        let msg = format!("Unknown escape in string: \\{}", c);
+                        it.next();
+                        return Err(it.tok_err(&msg, 2));
+                    }
+                    None => break,
+                };
                 it.next();
+                len += 1;
+                s.push(escaped);
             }
-            _ => break,
+            _ => s.push(c),
         }
     }
-    let len = symbol.len();
-    it.push(Token::Symbol(symbol), len);
+    Err(it.tok_err("Unterminated string", len))
 }
 
 fn tokenize_whitespace(it: &mut InputIterator<Token>) {
@@ -122,23 +151,62 @@ fn tokenize_whitespace(it: &mut InputIterator<Token>) {
             break;
         }
     }
-    let len = ws.len();
+    let len = ws.chars().count();
     it.push(Token::Whitespace(ws), len);
 }
 
-fn tokenize_number(it: &mut InputIterator<Token>) -> Result<(), TokenizeError> {
-    let mut num = String::new();
+/// Characters that end a number or a symbol.
+fn is_delimiter(c: char) -> bool {
+    c.is_whitespace() || "()'`,\";".contains(c)
+}
+
+/// Letters and the other characters an identifier may start with.
+fn is_initial(c: char) -> bool {
+    c.is_alphabetic() || "!$%&*/:<=>?^_~".contains(c)
+}
+
+fn is_subsequent(c: char) -> bool {
+    is_initial(c) || c.is_ascii_digit() || "+-.@".contains(c)
+}
+
+/// Whether `atom` would be a number if it were well formed: it starts with
+/// a digit, after an optional sign or dot.
+fn looks_numeric(atom: &str) -> bool {
+    let unsigned = atom.strip_prefix(['+', '-']).unwrap_or(atom);
+    let unsigned = unsigned.strip_prefix('.').unwrap_or(unsigned);
+    unsigned.starts_with(|c: char| c.is_ascii_digit())
+}
+
+/// Identifiers follow R7RS, e.g. `null?`, `set-car!`, `string->list`, and
+/// `+`, `-` and `...`.
+fn is_identifier(atom: &str) -> bool {
+    atom.chars().all(is_subsequent) && !atom.starts_with('@') && !looks_numeric(atom)
+}
+
+/// A number, a symbol or the dot of a dotted list: everything up to the
+/// next delimiter.
+fn tokenize_atom(it: &mut InputIterator<Token>) -> Result<(), TokenizeError> {
+    let mut atom = String::new();
     while let Some(&c) = it.peek() {
-        match c {
-            c if c.is_digit(10) => {
-                num.push(c);
-                it.next();
-            }
-            c if c.is_alphabetic() => return Err(tok_err("Invalid number", it.span(1))),
-            _ => break,
+        if is_delimiter(c) {
+            break;
         }
+        atom.push(c);
+        it.next();
     }
-    it.push(Token::Number(num.parse().unwrap()), num.len());
+    let len = atom.chars().count();
+    let token = if atom == "." {
+        Token::Dot
+    } else if let Some(n) = Number::parse(&atom) {
+        Token::Number(n)
+    } else if is_identifier(&atom) {
+        Token::Symbol(atom)
+    } else if looks_numeric(&atom) {
+        return Err(it.tok_err(&format!("Invalid number: {}", atom), len));
+    } else {
+        return Err(it.tok_err(&format!("Invalid symbol: {}", atom), len));
+    };
+    it.push(token, len);
     Ok(())
 }
 
@@ -151,7 +219,7 @@ fn tokenize_comment(it: &mut InputIterator<Token>) {
         comment.push(c);
         it.next();
     }
-    let len = comment.len();
+    let len = comment.chars().count();
     it.push(Token::Comment(comment), len);
 }
 
@@ -159,6 +227,8 @@ fn tokenize_comment(it: &mut InputIterator<Token>) {
 mod test {
     use super::*;
     use crate::parsing_utils::strip_spans;
+    use crate::parsing_utils::tok_err;
+    use crate::parsing_utils::Span;
 
     fn s(string: &str) -> String {
         string.to_string()
@@ -168,19 +238,25 @@ mod test {
     fn test_tokenize() {
         let cases = vec![
             (
-                "(\'`,+-*/=<>)",
+                "(\'`,+ - * / = < >)",
                 vec![
                     Token::OpenParen,
                     Token::Quote,
                     Token::Backquote,
                     Token::Comma,
-                    Token::Plus,
-                    Token::Minus,
-                    Token::Multiply,
-                    Token::Divide,
-                    Token::Equal,
-                    Token::LessThan,
-                    Token::GreaterThan,
+                    Token::Symbol(s("+")),
+                    Token::Whitespace(s(" ")),
+                    Token::Symbol(s("-")),
+                    Token::Whitespace(s(" ")),
+                    Token::Symbol(s("*")),
+                    Token::Whitespace(s(" ")),
+                    Token::Symbol(s("/")),
+                    Token::Whitespace(s(" ")),
+                    Token::Symbol(s("=")),
+                    Token::Whitespace(s(" ")),
+                    Token::Symbol(s("<")),
+                    Token::Whitespace(s(" ")),
+                    Token::Symbol(s(">")),
                     Token::CloseParen,
                 ],
             ),
@@ -209,13 +285,28 @@ mod test {
             (
                 "123,456,4,789456123",
                 vec![
-                    Token::Number(123),
+                    Token::Number(Number::Int(123)),
+                    Token::Comma,
+                    Token::Number(Number::Int(456)),
                     Token::Comma,
-                    Token::Number(456),
+                    Token::Number(Number::Int(4)),
                     Token::Comma,
-                    Token::Number(4),
+                    Token::Number(Number::Int(789456123)),
+                ],
+            ),
+            (
+                "`(a ,b ,@c)",
+                vec![
+                    Token::Backquote,
+                    Token::OpenParen,
+                    Token::Symbol(s("a")),
+                    Token::Whitespace(s(" ")),
                     Token::Comma,
-                    Token::Number(789456123),
+                    Token::Symbol(s("b")),
+                    Token::Whitespace(s(" ")),
+                    Token::CommaAt,
+                    Token::Symbol(s("c")),
+                    Token::CloseParen,
                 ],
             ),
             (
@@ -226,6 +317,55 @@ mod test {
                     Token::Symbol(s("world")),
                 ],
             ),
+            (
+                "(call/cc dynamic-wind)",
+                vec![
+                    Token::OpenParen,
+                    Token::Symbol(s("call/cc")),
+                    Token::Whitespace(s(" ")),
+                    Token::Symbol(s("dynamic-wind")),
+                    Token::CloseParen,
+                ],
+            ),
+            (
+                "(-12 +3 -x set-car! string->list ... (a . b))",
+                vec![
+                    Token::OpenParen,
+                    Token::Number(Number::Int(-12)),
+                    Token::Whitespace(s(" ")),
+                    Token::Number(Number::Int(3)),
+                    Token::Whitespace(s(" ")),
+                    Token::Symbol(s("-x")),
+                    Token::Whitespace(s(" ")),
+                    Token::Symbol(s("set-car!")),
+                    Token::Whitespace(s(" ")),
+                    Token::Symbol(s("string->list")),
+                    Token::Whitespace(s(" ")),
+                    Token::Symbol(s("...")),
+                    Token::Whitespace(s(" ")),
+                    Token::OpenParen,
+                    Token::Symbol(s("a")),
+                    Token::Whitespace(s(" ")),
+                    Token::Dot,
+                    Token::Whitespace(s(" ")),
+                    Token::Symbol(s("b")),
+                    Token::CloseParen,
+                    Token::CloseParen,
+                ],
+            ),
+            (
+                "(#\\a #\\()#\\space \"a\\\"b\\n\"",
+                vec![
+                    Token::OpenParen,
+                    Token::Char('a'),
+                    Token::Whitespace(s(" ")),
+                    Token::Char('('),
+                    Token::CloseParen,
+                    Token::Char(' '),
+                    Token::Whitespace(s(" ")),
+                    Token::String(s("a\"b\n")),
+                ],
+            ),
         ];
 
         for case in cases {
@@ -234,4 +374,35 @@ mod test {
             assert_eq!(strip_spans(tokens), expected);
         }
     }
+
+    #[test]
+    fn test_tokenize_non_ascii() {
+        // A comment and whitespace of multibyte characters, with spans
+        // counted in characters.
+        let tokens = tokenize("; é\n\u{3000}x").unwrap();
+        let spans: Vec<Span> = tokens.iter().map(|t| t.span).collect();
+        assert_eq!(spans, vec![Span::new(3, 3), Span::new(5, 2), Span::new(6, 1)]);
+        assert_eq!(
+            strip_spans(tokens),
+            vec![Token::Comment(s("; é")), Token::Whitespace(s("\n\u{3000}")), Token::Symbol(s("x"))]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_errors() {
+        let cases = vec![
+            ("(+ 1 1/0)", "Invalid number: 1/0", 8, 3),
+            ("(+ 12abc)", "Invalid number: 12abc", 8, 5),
+            ("(a[0])", "Invalid symbol: a[0]", 5, 4),
+            ("(#\\foo)", "Unknown character: #\\foo", 6, 5),
+            ("\"a\\qb\"", "Unknown escape in string: \\q", 4, 2),
+            ("\"abc", "Unterminated string", 4, 4),
+        ];
+
+        for (input, message, end, length) in cases {
+            let err = tokenize(input).unwrap_err();
+            let expected = tok_err(message, Span::new(end, length));
+            assert_eq!(format!("{:?}", err), format!("{:?}", expected));
+        }
+    }
 }
@@ -0,0 +1,337 @@
+use super::ast::SExpr;
+use super::heap::Handle;
+use super::heap::Heap;
+use super::heap::Marker;
+use super::heap::Trace;
+use super::interpreter::Continuation;
+use super::interpreter::Interpreter;
+use super::number::Number;
+
+use crate::parsing_utils::RuntimeError;
+use crate::parsing_utils::Span;
+use crate::parsing_utils::Spanned;
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Debug, Display, Formatter};
+use std::rc::Rc;
+
+/// A run-time value. Lists are built from pairs ending in `Nil`, the empty
+/// list. Pairs, vectors, hash tables and closures live on the `Heap`, and
+/// the value is a handle to them.
+#[derive(Clone)]
+pub(super) enum Value {
+    Nil,
+    Bool(bool),
+    Number(Number),
+    Symbol(String),
+    String(String),
+    Char(char),
+    Pair(Handle),
+    Vector(Handle),
+    HashTable(Handle),
+    Closure(Handle),
+    Primitive(Primitive),
+    Control(Control),
+    /// A continuation captured by `call/cc`. Calling it with a value makes
+    /// the `call/cc` return that value again.
+    Continuation(Rc<Continuation>),
+    /// The result of forms evaluated only for their effect, like `print`.
+    /// The REPL does not show it.
+    Void,
+}
+
+/// A mutable table from keys to values. Keys are atoms, compared as `eq?`
+/// compares them.
+#[derive(Default)]
+pub(super) struct HashTable {
+    /// Each entry keeps the key as a value too, to hand it back in
+    /// `hash-table-keys`.
+    pub entries: HashMap<Key, (Value, Value)>,
+}
+
+/// The atoms that can be hash table keys. Numbers are keyed by how they are
+/// written, so that `1` and `1.0` are different keys.
+#[derive(PartialEq, Eq, Hash)]
+pub(super) enum Key {
+    Nil,
+    Bool(bool),
+    Number(String),
+    Symbol(String),
+    String(String),
+    Char(char),
+}
+
+/// A `lambda` together with the environment it was evaluated in.
+pub(super) struct Closure {
+    pub name: Option<String>,
+    pub params: Params,
+    /// The form the closure was made from. Its body is `form[body..]`.
+    pub form: Rc<[SExpr]>,
+    pub body: usize,
+    pub env: EnvRef,
+}
+
+/// The parameters of a procedure. If there is a `rest` parameter, it is
+/// bound to the list of the arguments after the required ones.
+pub(super) struct Params {
+    pub required: Vec<String>,
+    pub rest: Option<String>,
+}
+
+/// A procedure implemented in Rust. It gets the evaluated arguments, with
+/// their spans for error reporting, and the span of the whole call.
+pub(super) type PrimitiveFn =
+    fn(&mut Interpreter, &[Spanned<Value>], Span) -> Result<Value, RuntimeError>;
+
+#[derive(Clone, Copy)]
+pub(super) struct Primitive {
+    pub name: &'static str,
+    pub func: PrimitiveFn,
+}
+
+/// The primitives that act on the evaluator's control state rather than
+/// only compute a value. The evaluator applies them itself.
+#[derive(Clone, Copy, PartialEq)]
+pub(super) enum Control {
+    /// `(call/cc f)` calls `f` with the continuation of the call.
+    CallCC,
+    /// `(dynamic-wind before thunk after)` calls `thunk`, and `before` and
+    /// `after` whenever control enters or leaves it.
+    DynamicWind,
+}
+
+impl Value {
+    /// Only `#f` is false, as in Scheme.
+    pub fn is_true(&self) -> bool {
+        !matches!(self, Value::Bool(false))
+    }
+
+    /// The name of the value's type, for error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Nil => "nil",
+            Value::Bool(_) => "boolean",
+            Value::Number(_) => "number",
+            Value::Symbol(_) => "symbol",
+            Value::String(_) => "string",
+            Value::Char(_) => "character",
+            Value::Pair(_) => "pair",
+            Value::Vector(_) => "vector",
+            Value::HashTable(_) => "hash table",
+            Value::Closure(_) | Value::Primitive(_) => "procedure",
+            Value::Control(_) | Value::Continuation(_) => "procedure",
+            Value::Void => "void",
+        }
+    }
+
+    /// The value as a hash table key, or `None` if it cannot be one.
+    pub fn to_key(&self) -> Option<Key> {
+        let key = match self {
+            Value::Nil => Key::Nil,
+            Value::Bool(b) => Key::Bool(*b),
+            Value::Number(n) => Key::Number(n.to_string()),
+            Value::Symbol(s) => Key::Symbol(s.clone()),
+            Value::String(s) => Key::String(s.clone()),
+            Value::Char(c) => Key::Char(*c),
+            _ => return None,
+        };
+        Some(key)
+    }
+}
+
+impl Heap {
+    /// Shows a value the way the REPL does. The parts of it on the heap are
+    /// looked up here.
+    pub fn show<'a>(&'a self, value: &'a Value) -> Shown<'a> {
+        Shown { heap: self, value }
+    }
+
+    /// The text `print` shows: like `show`, but strings and characters are
+    /// shown as they are.
+    pub fn to_display_string(&self, value: &Value) -> String {
+        match value {
+            Value::String(s) => s.clone(),
+            Value::Char(c) => c.to_string(),
+            v => self.show(v).to_string(),
+        }
+    }
+}
+
+pub(super) struct Shown<'a> {
+    heap: &'a Heap,
+    value: &'a Value,
+}
+
+impl Display for Shown<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write_value(f, self.heap, self.value, &mut HashSet::new())
+    }
+}
+
+/// Writes `value` as the REPL shows it. `open` holds the pairs and vectors
+/// being written around it: a list or vector that leads back into one of
+/// them shows "..." where it would start to repeat.
+fn write_value(
+    f: &mut Formatter,
+    heap: &Heap,
+    value: &Value,
+    open: &mut HashSet<Handle>,
+) -> fmt::Result {
+    match value {
+        Value::Pair(handle) if open.contains(handle) => write!(f, "..."),
+        Value::Pair(handle) => {
+            let (car, cdr) = heap.pair(*handle);
+            open.insert(*handle);
+            let mut spine = vec![*handle];
+            write!(f, "(")?;
+            write_value(f, heap, car, open)?;
+            let mut tail = cdr;
+            loop {
+                match tail {
+                    Value::Nil => break,
+                    Value::Pair(handle) if open.contains(handle) => {
+                        write!(f, " ...")?;
+                        break;
+                    }
+                    Value::Pair(handle) => {
+                        let (car, cdr) = heap.pair(*handle);
+                        open.insert(*handle);
+                        spine.push(*handle);
+                        write!(f, " ")?;
+                        write_value(f, heap, car, open)?;
+                        tail = cdr;
+                    }
+                    v => {
+                        write!(f, " . ")?;
+                        write_value(f, heap, v, open)?;
+                        break;
+                    }
+                }
+            }
+            for handle in spine {
+                open.remove(&handle);
+            }
+            write!(f, ")")
+        }
+        Value::Vector(handle) if open.contains(handle) => write!(f, "..."),
+        Value::Vector(handle) => {
+            open.insert(*handle);
+            write!(f, "#(")?;
+            for (i, v) in heap.vector(*handle).iter().enumerate() {
+                if i > 0 {
+                    write!(f, " ")?;
+                }
+                write_value(f, heap, v, open)?;
+            }
+            open.remove(handle);
+            write!(f, ")")
+        }
+        Value::HashTable(handle) => {
+            write!(f, "#<hash-table {}>", heap.hash_table(*handle).entries.len())
+        }
+        Value::Closure(handle) => write!(f, "{:?}", heap.closure(*handle)),
+        v => write!(f, "{:?}", v),
+    }
+}
+
+/// Shows atoms as the REPL does. Values on the heap can only be shown with
+/// `Heap::show`, so they are shown by their handle here.
+impl Debug for Value {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Value::Nil => write!(f, "()"),
+            Value::Bool(true) => write!(f, "#t"),
+            Value::Bool(false) => write!(f, "#f"),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::Symbol(s) => write!(f, "{}", s),
+            Value::String(s) => write!(f, "{:?}", s),
+            Value::Char(' ') => write!(f, "#\\space"),
+            Value::Char('\n') => write!(f, "#\\newline"),
+            Value::Char('\t') => write!(f, "#\\tab"),
+            Value::Char(c) => write!(f, "#\\{}", c),
+            Value::Pair(handle) => write!(f, "#<pair {:?}>", handle),
+            Value::Vector(handle) => write!(f, "#<vector {:?}>", handle),
+            Value::HashTable(handle) => write!(f, "#<hash-table {:?}>", handle),
+            Value::Closure(handle) => write!(f, "#<procedure {:?}>", handle),
+            Value::Primitive(p) => write!(f, "#<primitive {}>", p.name),
+            Value::Control(Control::CallCC) => write!(f, "#<primitive call/cc>"),
+            Value::Control(Control::DynamicWind) => write!(f, "#<primitive dynamic-wind>"),
+            Value::Continuation(_) => write!(f, "#<continuation>"),
+            Value::Void => Ok(()),
+        }
+    }
+}
+
+impl Debug for Closure {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "#<procedure {}>", name),
+            None => write!(f, "#<procedure>"),
+        }
+    }
+}
+
+impl Trace for Value {
+    fn trace(&self, marker: &mut Marker) {
+        match self {
+            Value::Pair(handle)
+            | Value::Vector(handle)
+            | Value::HashTable(handle)
+            | Value::Closure(handle) => marker.mark(*handle),
+            Value::Continuation(k) if marker.first_visit(k) => k.trace(marker),
+            _ => {}
+        }
+    }
+}
+
+/// An environment on the heap.
+pub(super) type EnvRef = Handle;
+
+/// A lexical environment: the bindings of one scope and the enclosing one.
+#[derive(Clone)]
+pub(super) struct Env {
+    vars: HashMap<String, Value>,
+    pub parent: Option<EnvRef>,
+}
+
+impl Env {
+    pub fn new(parent: Option<EnvRef>) -> Env {
+        Env {
+            vars: HashMap::new(),
+            parent,
+        }
+    }
+
+    pub fn define(&mut self, name: &str, value: Value) {
+        self.vars.insert(name.to_string(), value);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.vars.get(name)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Value> {
+        self.vars.get_mut(name)
+    }
+}
+
+impl Trace for Env {
+    fn trace(&self, marker: &mut Marker) {
+        for value in self.vars.values() {
+            value.trace(marker);
+        }
+        if let Some(parent) = self.parent {
+            marker.mark(parent);
+        }
+    }
+}
+
+/// Closures refer back to the environments they are stored in, so only the
+/// names bound in this scope are shown.
+impl Debug for Env {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let mut names: Vec<&String> = self.vars.keys().collect();
+        names.sort();
+        f.debug_struct("Env").field("names", &names).finish()
+    }
+}
@@ -1,9 +1,13 @@
 mod app;
 mod colors;
+mod derivation;
 mod errors;
+mod trace;
 mod tui;
 mod untyped_arithmetic;
+mod typed_arithmetic;
 mod untyped_lambda_calculus;
+mod simply_typed_lambda_calculus;
 mod parsing_utils;
 mod lisp;
 mod evaluator;
@@ -43,7 +47,7 @@ fn main2() {
             evaluator = pick_evaluator();
             continue;
         }
-        let out = evaluator.run(input);
+        let out = evaluator::run_input(evaluator.as_mut(), input);
         println!("{}", out);
     }
 }
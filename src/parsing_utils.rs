@@ -2,7 +2,7 @@ use crate::colors::*;
 use std::fmt::{self, Display, Formatter};
 use std::fmt::Debug;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Span {
     start: usize,
     length: usize,
@@ -24,6 +24,28 @@ impl Span {
             length: end - start,
         }
     }
+
+    /// The span of the same text in a longer text where it starts at
+    /// `offset`.
+    pub fn shift(self, offset: usize) -> Span {
+        Span {
+            start: self.start + offset,
+            ..self
+        }
+    }
+
+    /// The opposite of `shift`.
+    pub fn unshift(self, offset: usize) -> Span {
+        Span {
+            start: self.start - offset,
+            ..self
+        }
+    }
+
+    /// Whether the span lies in the `length` characters from `start`.
+    pub fn is_within(self, start: usize, length: usize) -> bool {
+        self.start >= start && self.start + self.length <= start + length
+    }
 }
 
 #[derive(Debug)]
@@ -33,24 +55,49 @@ pub struct TokenizeError {
 }
 
 pub fn tokenize_error_to_string(err: TokenizeError, input: &str) -> String {
+    error_to_string(&err.message, err.span, input)
+}
+
+/// Renders an error message followed by the input, with the span the error
+/// refers to highlighted and underlined. Spans count characters, not bytes.
+fn error_to_string(message: &str, span: Span, input: &str) -> String {
     let mut out = String::new();
-    out.push_str(format!("Error: {}\n", err.message).as_str());
+    out.push_str(format!("Error: {}\n", message).as_str());
+
+    let chars: Vec<char> = input.chars().collect();
+    let mut start = span.start.min(chars.len());
+    let mut end = (span.start + span.length).min(chars.len());
+    let mut length = span.length;
 
-    let (start, end) = (err.span.start, err.span.start + err.span.length);
+    // Of a longer input, like a file, only show the line the error is on.
+    let line_start = chars[..start].iter().rposition(|&c| c == '\n').map_or(0, |i| i + 1);
+    let line_end = chars[start..].iter().position(|&c| c == '\n').map_or(chars.len(), |i| start + i);
+    let chars = if line_start > 0 || line_end < chars.len() {
+        let line = chars[..line_start].iter().filter(|&&c| c == '\n').count() + 1;
+        out.push_str(&format!("line {}:\n", line));
+        start -= line_start;
+        end = end.min(line_end) - line_start;
+        length = length.min(end - start).max(1);
+        &chars[line_start..line_end]
+    } else {
+        &chars[..]
+    };
+    let text = |range: &[char]| range.iter().collect::<String>();
 
     // Add input text
     out.push_str(CYAN);
-    out.push_str(&input[..start]);
+    out.push_str(&text(&chars[..start]));
     out.push_str(RED);
-    out.push_str(&input[start..end]);
+    out.push_str(&text(&chars[start..end]));
     out.push_str(CYAN);
-    out.push_str(&input[end..]);
+    out.push_str(&text(&chars[end..]));
 
     // Add ^ marker
-    out.push_str("\n");
+    out.push('\n');
     out.push_str(&" ".repeat(start));
     out.push_str(RED);
-    out.push_str(&"^".repeat(err.span.length));
+    out.push_str(&"^".repeat(length));
+    out.push_str(RESET);
 
     out
 }
@@ -62,7 +109,7 @@ pub fn tok_err(message: &str, span: Span) -> TokenizeError {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Spanned<T> {
     pub item: T,
     pub span: Span,
@@ -79,7 +126,7 @@ pub struct InputIterator<'a, T> {
     pub tokens: Vec<Spanned<T>>
 }
 impl<T> InputIterator<'_, T> {
-    pub fn new(input: &str) -> InputIterator<T> {
+    pub fn new(input: &str) -> InputIterator<'_, T> {
         InputIterator {
             chars: input.chars().peekable(),
             offset: 0,
@@ -159,24 +206,56 @@ pub fn parse_err(message: &str, span: Span) -> ParseError {
 }
 
 pub fn parse_error_to_string(err: ParseError, input: &str) -> String {
-    let mut out = String::new();
-    out.push_str(format!("Error: {}\n", err.message).as_str());
+    error_to_string(&err.message, err.span, input)
+}
 
-    let (start, end) = (err.span.start, err.span.start + err.span.length);
+#[derive(Debug)]
+pub struct TypeError {
+    message: String,
+    span: Span,
+}
 
-    // Add input text
-    out.push_str(CYAN);
-    out.push_str(&input[..start]);
-    out.push_str(RED);
-    out.push_str(&input[start..end]);
-    out.push_str(CYAN);
-    out.push_str(&input[end..]);
+pub fn type_err(message: &str, span: Span) -> TypeError {
+    TypeError {
+        message: message.to_string(),
+        span,
+    }
+}
 
-    // Add ^ marker
-    out.push_str("\n");
-    out.push_str(&" ".repeat(start));
-    out.push_str(RED);
-    out.push_str(&"^".repeat(err.span.length));
+pub fn type_error_to_string(err: TypeError, input: &str) -> String {
+    error_to_string(&err.message, err.span, input)
+}
 
-    out
+#[derive(Debug)]
+pub struct RuntimeError {
+    message: String,
+    span: Span,
+}
+
+pub fn runtime_err(message: &str, span: Span) -> RuntimeError {
+    RuntimeError {
+        message: message.to_string(),
+        span,
+    }
+}
+
+/// Reports `err` at `span` instead, prefixed with `context`. For errors
+/// raised by code whose source is not the input being reported on.
+pub fn runtime_err_within(context: &str, err: RuntimeError, span: Span) -> RuntimeError {
+    runtime_err(&format!("{}: {}", context, err.message), span)
+}
+
+impl RuntimeError {
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    /// The same error, reported at `span`.
+    pub fn at(self, span: Span) -> RuntimeError {
+        RuntimeError { span, ..self }
+    }
+}
+
+pub fn runtime_error_to_string(err: RuntimeError, input: &str) -> String {
+    error_to_string(&err.message, err.span, input)
 }
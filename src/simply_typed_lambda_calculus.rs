@@ -0,0 +1,627 @@
+use crate::derivation::Derivation;
+use crate::evaluator::parse_command;
+use crate::evaluator::parse_toggle;
+use crate::evaluator::Evaluator;
+use crate::parsing_utils::parse_err;
+use crate::parsing_utils::parse_error_to_string;
+use crate::parsing_utils::stoken;
+use crate::parsing_utils::tokenize_error_to_string;
+use crate::parsing_utils::type_err;
+use crate::parsing_utils::type_error_to_string;
+use crate::parsing_utils::InputIterator;
+use crate::parsing_utils::ParseError;
+use crate::parsing_utils::Span;
+use crate::parsing_utils::Spanned;
+use crate::parsing_utils::TokenizeError;
+use crate::parsing_utils::TypeError;
+
+use std::fmt::{self, Display, Formatter};
+use std::iter::Peekable;
+use std::slice::Iter;
+
+/// The simply typed lambda calculus with booleans, λ→ (TAPL ch. 9 and 10).
+#[derive(Debug, Default)]
+pub struct SimplyTypedLambdaCalculus {
+    /// Whether the typing derivation is printed after the type.
+    typing: bool,
+    /// Whether derivations are printed as LaTeX instead of ASCII trees.
+    latex: bool,
+}
+
+impl SimplyTypedLambdaCalculus {
+    fn run_command(&mut self, name: &str, arg: &str) -> String {
+        match name {
+            "latex" => match parse_toggle(arg, self.latex) {
+                Some(latex) => {
+                    self.latex = latex;
+                    format!("latex: {}", if latex { "on" } else { "off" })
+                }
+                None => format!("Error: Expected 'on' or 'off', got '{}'", arg),
+            },
+            _ => format!("Error: Unknown command ':{}'", name),
+        }
+    }
+}
+
+impl Evaluator for SimplyTypedLambdaCalculus {
+    fn run(&mut self, input: &str) -> String {
+        if let Some((name, arg)) = parse_command(input) {
+            return self.run_command(name, arg);
+        }
+        let mut out = String::new();
+        let tokens = tokenize(input);
+        if tokens.is_err() {
+            let err = tokenize_error_to_string(tokens.err().unwrap(), input);
+            out.push_str(err.as_str());
+            return out;
+        }
+        let tokens = tokens.unwrap();
+
+        let term = parse(&tokens);
+        if term.is_err() {
+            let err = parse_error_to_string(term.err().unwrap(), input);
+            out.push_str(err.as_str());
+            return out;
+        }
+        let term = term.unwrap();
+        out.push_str("parsed: ");
+        out.push_str(&format!("{}\n", term.item));
+
+        let typing = typing(&mut Vec::new(), &term);
+        if typing.is_err() {
+            let err = type_error_to_string(typing.err().unwrap(), input);
+            out.push_str(err.as_str());
+            return out;
+        }
+        let (ty, derivation) = typing.unwrap();
+        out.push_str(&format!("type: {}\n", ty));
+        if self.typing {
+            out.push_str("typing:\n");
+            out.push_str(&derivation.render(self.latex));
+        }
+
+        let result = eval(&term);
+        out.push_str(&format!("result: {} : {}\n", result.item, ty));
+        out
+    }
+    fn __debug__(&self) -> String {
+        format!("{:?}", self)
+    }
+    fn name(&self) -> String {
+        "Simply Typed Lambda Calculus".to_string()
+    }
+
+    fn typing(&self) -> Option<bool> {
+        Some(self.typing)
+    }
+
+    fn set_typing(&mut self, typing: bool) {
+        self.typing = typing;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Type {
+    Bool,
+    Arrow(Box<Type>, Box<Type>),
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Type::Bool => write!(f, "Bool"),
+            Type::Arrow(t1, t2) => match **t1 {
+                Type::Arrow(_, _) => write!(f, "({}) -> {}", t1, t2),
+                _ => write!(f, "{} -> {}", t1, t2),
+            },
+        }
+    }
+}
+
+fn arrow(t1: Type, t2: Type) -> Type {
+    Type::Arrow(Box::new(t1), Box::new(t2))
+}
+
+type STerm = Spanned<Term>;
+
+#[derive(Debug, Clone)]
+enum Term {
+    Var(String),
+    Abs(String, Type, Box<STerm>),
+    App(Box<STerm>, Box<STerm>),
+    True,
+    False,
+    If(Box<STerm>, Box<STerm>, Box<STerm>),
+}
+
+impl Display for Term {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write_term(f, self, true)
+    }
+}
+
+/// Writes `term` with only the parentheses needed to parse it back.
+/// Abstractions and conditionals extend as far right as possible, so they
+/// need parentheses unless they are in `tail` position.
+fn write_term(f: &mut Formatter, term: &Term, tail: bool) -> fmt::Result {
+    match term {
+        Term::Var(id) => write!(f, "{}", id),
+        Term::True => write!(f, "true"),
+        Term::False => write!(f, "false"),
+        Term::Abs(_, _, _) | Term::If(_, _, _) if !tail => {
+            write!(f, "(")?;
+            write_term(f, term, true)?;
+            write!(f, ")")
+        }
+        Term::Abs(id, ty, body) => {
+            write!(f, "λ{}:{}. ", id, ty)?;
+            write_term(f, &body.item, true)
+        }
+        Term::If(t1, t2, t3) => {
+            write!(f, "if ")?;
+            write_term(f, &t1.item, true)?;
+            write!(f, " then ")?;
+            write_term(f, &t2.item, true)?;
+            write!(f, " else ")?;
+            write_term(f, &t3.item, true)
+        }
+        Term::App(t1, t2) => {
+            write_term(f, &t1.item, false)?;
+            write!(f, " ")?;
+            match t2.item {
+                Term::App(_, _) => write!(f, "({})", t2.item),
+                _ => write_term(f, &t2.item, tail),
+            }
+        }
+    }
+}
+
+// Typing
+
+/// The typing context Γ, with the most recent binding last.
+type Context = Vec<(String, Type)>;
+
+/// Derives the type of `term` in the context `ctx`, together with the
+/// derivation of `Γ ⊢ t : T`.
+fn typing(ctx: &mut Context, term: &STerm) -> Result<(Type, Derivation), TypeError> {
+    let (ty, rule, premises) = match &term.item {
+        Term::Var(x) => match ctx.iter().rev().find(|(y, _)| y == x) {
+            Some((_, ty)) => (ty.clone(), "T-Var", Vec::new()),
+            None => return Err(type_err(&format!("Unbound variable '{}'", x), term.span)),
+        },
+        Term::Abs(x, ty, body) => {
+            ctx.push((x.clone(), ty.clone()));
+            let body_typing = typing(ctx, body);
+            ctx.pop();
+            let (body_ty, d) = body_typing?;
+            (arrow(ty.clone(), body_ty), "T-Abs", vec![d])
+        }
+        Term::App(t1, t2) => {
+            let (ty1, d1) = typing(ctx, t1)?;
+            let (ty2, d2) = typing(ctx, t2)?;
+            match ty1 {
+                Type::Arrow(ty11, ty12) if *ty11 == ty2 => (*ty12, "T-App", vec![d1, d2]),
+                Type::Arrow(ty11, _) => {
+                    let msg = format!("Expected an argument of type {}, got {}", ty11, ty2);
+                    return Err(type_err(&msg, t2.span));
+                }
+                _ => {
+                    let msg = format!("Expected a function, got a term of type {}", ty1);
+                    return Err(type_err(&msg, t1.span));
+                }
+            }
+        }
+        Term::True => (Type::Bool, "T-True", Vec::new()),
+        Term::False => (Type::Bool, "T-False", Vec::new()),
+        Term::If(t1, t2, t3) => {
+            let (ty1, d1) = typing(ctx, t1)?;
+            if ty1 != Type::Bool {
+                let msg = format!("Expected a condition of type Bool, got {}", ty1);
+                return Err(type_err(&msg, t1.span));
+            }
+            let (ty2, d2) = typing(ctx, t2)?;
+            let (ty3, d3) = typing(ctx, t3)?;
+            if ty2 != ty3 {
+                let msg = format!("Branches have different types: {} and {}", ty2, ty3);
+                return Err(type_err(&msg, t3.span));
+            }
+            (ty2, "T-If", vec![d1, d2, d3])
+        }
+    };
+    let conclusion = format!("{} ⊢ {} : {}", context_to_string(ctx), term.item, ty);
+    Ok((ty, Derivation::new(rule, conclusion, premises)))
+}
+
+/// Γ as a comma separated list of bindings, or ∅ if it is empty.
+fn context_to_string(ctx: &Context) -> String {
+    if ctx.is_empty() {
+        return "∅".to_string();
+    }
+    let bindings: Vec<String> = ctx.iter().map(|(x, ty)| format!("{}:{}", x, ty)).collect();
+    bindings.join(", ")
+}
+
+// Evaluation
+
+/// Evaluates a well-typed closed term to a value, call-by-value. Well-typed
+/// terms cannot get stuck and always terminate (TAPL §12.1).
+fn eval(term: &STerm) -> STerm {
+    let mut t = term.clone();
+    while let Some(next) = eval1(&t) {
+        t = next;
+    }
+    t
+}
+
+fn eval1(term: &STerm) -> Option<STerm> {
+    let item = match &term.item {
+        Term::App(t1, t2) => match &t1.item {
+            // E-AppAbs
+            Term::Abs(x, _, body) if isval(t2) => return Some(substitute(body, x, t2)),
+            // E-App2
+            _ if isval(t1) => app(*t1.clone(), eval1(t2)?),
+            // E-App1
+            _ => app(eval1(t1)?, *t2.clone()),
+        },
+        Term::If(t1, t2, t3) => match t1.item {
+            // E-IfTrue
+            Term::True => return Some(*t2.clone()),
+            // E-IfFalse
+            Term::False => return Some(*t3.clone()),
+            // E-If
+            _ => Term::If(Box::new(eval1(t1)?), t2.clone(), t3.clone()),
+        },
+        Term::Var(_) | Term::Abs(_, _, _) | Term::True | Term::False => return None,
+    };
+    Some(stoken(item, term.span))
+}
+
+fn isval(term: &STerm) -> bool {
+    matches!(term.item, Term::Abs(_, _, _) | Term::True | Term::False)
+}
+
+fn app(t1: STerm, t2: STerm) -> Term {
+    Term::App(Box::new(t1), Box::new(t2))
+}
+
+/// `[x ↦ value] term`. Only closed values are ever substituted during
+/// call-by-value evaluation of a closed term, so no variable can be captured.
+fn substitute(term: &STerm, x: &str, value: &STerm) -> STerm {
+    let item = match &term.item {
+        Term::Var(y) if y == x => return value.clone(),
+        Term::Abs(y, _, _) if y == x => return term.clone(),
+        Term::Abs(y, ty, body) => {
+            Term::Abs(y.clone(), ty.clone(), Box::new(substitute(body, x, value)))
+        }
+        Term::App(t1, t2) => app(substitute(t1, x, value), substitute(t2, x, value)),
+        Term::If(t1, t2, t3) => Term::If(
+            Box::new(substitute(t1, x, value)),
+            Box::new(substitute(t2, x, value)),
+            Box::new(substitute(t3, x, value)),
+        ),
+        Term::Var(_) | Term::True | Term::False => return term.clone(),
+    };
+    stoken(item, term.span)
+}
+
+// Tokenizer
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Lambda,
+    Dot,
+    Colon,
+    Arrow,
+    LParen,
+    RParen,
+    True,
+    False,
+    If,
+    Then,
+    Else,
+    Bool,
+    Identifier(String),
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Token::Lambda => write!(f, "λ"),
+            Token::Dot => write!(f, "."),
+            Token::Colon => write!(f, ":"),
+            Token::Arrow => write!(f, "->"),
+            Token::LParen => write!(f, "("),
+            Token::RParen => write!(f, ")"),
+            Token::True => write!(f, "true"),
+            Token::False => write!(f, "false"),
+            Token::If => write!(f, "if"),
+            Token::Then => write!(f, "then"),
+            Token::Else => write!(f, "else"),
+            Token::Bool => write!(f, "Bool"),
+            Token::Identifier(id) => write!(f, "{}", id),
+        }
+    }
+}
+
+type SToken = Spanned<Token>;
+
+fn tokenize(input: &str) -> Result<Vec<SToken>, TokenizeError> {
+    let mut it = InputIterator::new(input);
+    while let Some(&c) = it.peek() {
+        match c {
+            'λ' | '\\' => it.next_and_push(Token::Lambda, 1),
+            '.' => it.next_and_push(Token::Dot, 1),
+            ':' => it.next_and_push(Token::Colon, 1),
+            '(' => it.next_and_push(Token::LParen, 1),
+            ')' => it.next_and_push(Token::RParen, 1),
+            '-' => {
+                it.next();
+                if it.peek() != Some(&'>') {
+                    return Err(it.tok_err("Expected '->'", 1));
+                }
+                it.next_and_push(Token::Arrow, 2);
+            }
+            c if c.is_whitespace() => {
+                it.next();
+            }
+            c if c.is_alphabetic() => {
+                let mut identifier = String::new();
+                while let Some(&c) = it.peek() {
+                    if c.is_alphanumeric() {
+                        identifier.push(c);
+                        it.next();
+                    } else {
+                        break;
+                    }
+                }
+                let len = identifier.chars().count();
+                let token = match identifier.as_str() {
+                    "true" => Token::True,
+                    "false" => Token::False,
+                    "if" => Token::If,
+                    "then" => Token::Then,
+                    "else" => Token::Else,
+                    "Bool" => Token::Bool,
+                    _ => Token::Identifier(identifier),
+                };
+                it.push(token, len);
+            }
+            _ => {
+                it.next();
+                return Err(it.tok_err("Unexpected character", 1));
+            }
+        }
+    }
+    Ok(it.tokens)
+}
+
+// Parser
+//
+// term        ::= "λ" identifier ":" type "." term
+//               | "if" term "then" term "else" term
+//               | application
+// application ::= atom+ ("λ" ... | "if" ...)?
+// atom        ::= identifier | "true" | "false" | "(" term ")"
+// type        ::= "Bool" ("->" type)? | "(" type ")" ("->" type)?
+
+type TokenIterator<'a> = Peekable<Iter<'a, SToken>>;
+
+fn parse(tokens: &[SToken]) -> Result<STerm, ParseError> {
+    let mut it = tokens.iter().peekable();
+    let term = parse_term(&mut it)?;
+    if let Some(t) = it.next() {
+        return Err(parse_err(&format!("Unexpected token '{}'", t.item), t.span));
+    }
+    Ok(term)
+}
+
+fn parse_term(it: &mut TokenIterator) -> Result<STerm, ParseError> {
+    match peek(it)?.item {
+        Token::Lambda => parse_abstraction(it),
+        Token::If => parse_if(it),
+        _ => parse_application(it),
+    }
+}
+
+fn parse_abstraction(it: &mut TokenIterator) -> Result<STerm, ParseError> {
+    let start = expect_token(it, Token::Lambda)?;
+    let id = expect_identifier(it)?;
+    expect_token(it, Token::Colon)?;
+    let ty = parse_type(it)?;
+    expect_token(it, Token::Dot)?;
+    let body = parse_term(it)?;
+    let span = Span::merge(&[start, body.span]);
+    Ok(stoken(Term::Abs(id, ty, Box::new(body)), span))
+}
+
+fn parse_if(it: &mut TokenIterator) -> Result<STerm, ParseError> {
+    let start = expect_token(it, Token::If)?;
+    let t1 = parse_term(it)?;
+    expect_token(it, Token::Then)?;
+    let t2 = parse_term(it)?;
+    expect_token(it, Token::Else)?;
+    let t3 = parse_term(it)?;
+    let span = Span::merge(&[start, t3.span]);
+    let term = Term::If(Box::new(t1), Box::new(t2), Box::new(t3));
+    Ok(stoken(term, span))
+}
+
+fn parse_application(it: &mut TokenIterator) -> Result<STerm, ParseError> {
+    let mut exp = parse_atom(it)?;
+    while let Some(t) = it.peek() {
+        let arg = match t.item {
+            Token::Identifier(_) | Token::True | Token::False | Token::LParen => parse_atom(it)?,
+            Token::Lambda => parse_abstraction(it)?,
+            Token::If => parse_if(it)?,
+            _ => break,
+        };
+        let span = Span::merge(&[exp.span, arg.span]);
+        exp = stoken(app(exp, arg), span);
+    }
+    Ok(exp)
+}
+
+fn parse_atom(it: &mut TokenIterator) -> Result<STerm, ParseError> {
+    let t = it.next().ok_or_else(end_of_input)?;
+    let term = match &t.item {
+        Token::Identifier(id) => Term::Var(id.clone()),
+        Token::True => Term::True,
+        Token::False => Term::False,
+        Token::LParen => {
+            let term = parse_term(it)?;
+            let end = expect_token(it, Token::RParen)?;
+            return Ok(stoken(term.item, Span::merge(&[t.span, end])));
+        }
+        token => return Err(parse_err(&format!("Unexpected token '{}'", token), t.span)),
+    };
+    Ok(stoken(term, t.span))
+}
+
+fn parse_type(it: &mut TokenIterator) -> Result<Type, ParseError> {
+    let t = it.next().ok_or_else(end_of_input)?;
+    let ty = match t.item {
+        Token::Bool => Type::Bool,
+        Token::LParen => {
+            let ty = parse_type(it)?;
+            expect_token(it, Token::RParen)?;
+            ty
+        }
+        _ => {
+            return Err(parse_err(
+                &format!("Expected a type, got '{}'", t.item),
+                t.span,
+            ))
+        }
+    };
+    if let Some(Token::Arrow) = it.peek().map(|t| &t.item) {
+        it.next();
+        return Ok(arrow(ty, parse_type(it)?));
+    }
+    Ok(ty)
+}
+
+fn peek<'a>(it: &mut TokenIterator<'a>) -> Result<&'a SToken, ParseError> {
+    it.peek().copied().ok_or_else(end_of_input)
+}
+
+fn end_of_input() -> ParseError {
+    parse_err("Unexpected end of input", Span::new(0, 0))
+}
+
+fn expect_identifier(it: &mut TokenIterator) -> Result<String, ParseError> {
+    let t = it.next().ok_or_else(end_of_input)?;
+    match &t.item {
+        Token::Identifier(id) => Ok(id.clone()),
+        _ => Err(parse_err("Expected identifier", t.span)),
+    }
+}
+
+fn expect_token(it: &mut TokenIterator, expected: Token) -> Result<Span, ParseError> {
+    let t = it.next().ok_or_else(end_of_input)?;
+    if t.item != expected {
+        let msg = format!("Unexpected token '{}', expected '{}'", t.item, expected);
+        return Err(parse_err(&msg, t.span));
+    }
+    Ok(t.span)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn check(input: &str) -> Result<(Type, String), TypeError> {
+        let term = parse(&tokenize(input).unwrap()).unwrap();
+        let (ty, _) = typing(&mut Vec::new(), &term)?;
+        Ok((ty, eval(&term).item.to_string()))
+    }
+
+    #[test]
+    fn test_type_and_eval() {
+        let cases = vec![
+            ("true", "Bool", "true"),
+            ("λx:Bool. x", "Bool -> Bool", "λx:Bool. x"),
+            ("(λx:Bool. if x then false else true) true", "Bool", "false"),
+            (
+                "λf:Bool -> Bool. f (f true)",
+                "(Bool -> Bool) -> Bool",
+                "λf:Bool -> Bool. f (f true)",
+            ),
+            (
+                "(λf:Bool -> Bool. λx:Bool. f (f x)) (λb:Bool. if b then false else true) true",
+                "Bool",
+                "true",
+            ),
+            (
+                "λx:Bool. λx:Bool -> Bool. x",
+                "Bool -> (Bool -> Bool) -> Bool -> Bool",
+                "λx:Bool. λx:Bool -> Bool. x",
+            ),
+        ];
+
+        for (input, ty, value) in cases {
+            let (t, v) = check(input).unwrap();
+            assert_eq!(t.to_string(), ty, "{}", input);
+            assert_eq!(v, value, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_typing_derivation() {
+        let term = parse(&tokenize("λf:Bool -> Bool. λx:Bool. f x").unwrap()).unwrap();
+        let (_, d) = typing(&mut Vec::new(), &term).unwrap();
+        assert_eq!(
+            d.to_string(),
+            "∅ ⊢ λf:Bool -> Bool. λx:Bool. f x : (Bool -> Bool) -> Bool -> Bool  (T-Abs)\n\
+             \x20 f:Bool -> Bool ⊢ λx:Bool. f x : Bool -> Bool  (T-Abs)\n\
+             \x20   f:Bool -> Bool, x:Bool ⊢ f x : Bool  (T-App)\n\
+             \x20     f:Bool -> Bool, x:Bool ⊢ f : Bool -> Bool  (T-Var)\n\
+             \x20     f:Bool -> Bool, x:Bool ⊢ x : Bool  (T-Var)\n"
+        );
+    }
+
+    #[test]
+    fn test_type_errors() {
+        let cases = vec![
+            ("x", "Unbound variable 'x'", "x"),
+            (
+                "true false",
+                "Expected a function, got a term of type Bool",
+                "true",
+            ),
+            (
+                "(λx:Bool. x) (λx:Bool. x)",
+                "Expected an argument of type Bool, got Bool -> Bool",
+                "(λx:Bool. x)",
+            ),
+            (
+                "if λx:Bool. x then true else false",
+                "Expected a condition of type Bool, got Bool -> Bool",
+                "λx:Bool. x",
+            ),
+            (
+                "λx:Bool. if x then x else λy:Bool. y",
+                "Branches have different types: Bool and Bool -> Bool",
+                "λy:Bool. y",
+            ),
+        ];
+
+        for (input, message, offending) in cases {
+            let err = check(input).unwrap_err();
+            let rendered = type_error_to_string(err, input);
+            let offending_start = input.rfind(offending).unwrap();
+            let column = input[..offending_start].chars().count();
+            let marker = format!(
+                "\n{}{}{}",
+                " ".repeat(column),
+                crate::colors::RED,
+                "^".repeat(offending.chars().count())
+            );
+            assert!(
+                rendered.starts_with(&format!("Error: {}\n", message)),
+                "{}",
+                rendered
+            );
+            assert!(rendered.contains(&marker), "{}", rendered);
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use crate::colors::*;
+use std::fmt::{self, Display, Formatter};
+
+/// The result of a single evaluation step: the new term, the name of the
+/// computation rule that was applied and the position of the contracted
+/// redex, given as child indices from the root of the term.
+pub struct Step<T> {
+    pub term: T,
+    pub rule: &'static str,
+    pub path: Vec<usize>,
+}
+
+impl<T> Step<T> {
+    pub fn new(term: T, rule: &'static str) -> Step<T> {
+        Step {
+            term,
+            rule,
+            path: Vec::new(),
+        }
+    }
+
+    /// Lifts a step taken inside the `index`th subterm to the enclosing term.
+    pub fn congruence(mut self, index: usize, wrap: impl FnOnce(T) -> T) -> Step<T> {
+        self.path.insert(0, index);
+        self.term = wrap(self.term);
+        self
+    }
+}
+
+/// Terms that can be written with one of their subterms highlighted.
+pub trait WriteTerm {
+    /// Writes the term, highlighting the subterm that `path` leads to, if
+    /// any.
+    fn write_term(&self, f: &mut Formatter, path: Option<&[usize]>) -> fmt::Result;
+}
+
+/// Displays a term with the subterm at `path` marked in colour.
+pub struct Highlight<'a, T> {
+    pub term: &'a T,
+    pub path: &'a [usize],
+}
+
+impl<T: WriteTerm> Display for Highlight<'_, T> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.term.write_term(f, Some(self.path))
+    }
+}
+
+/// Writes the highlighted subterm with `write`.
+pub fn write_highlighted(
+    f: &mut Formatter,
+    write: impl FnOnce(&mut Formatter) -> fmt::Result,
+) -> fmt::Result {
+    write!(f, "{}", RED)?;
+    write(f)?;
+    write!(f, "{}", RESET)
+}
+
+/// The path to the highlighted subterm from the `index`th child of the
+/// term `path` leads into, if the highlighted subterm is inside that child.
+pub fn child_path(path: Option<&[usize]>, index: usize) -> Option<&[usize]> {
+    match path {
+        Some([first, rest @ ..]) if *first == index => Some(rest),
+        _ => None,
+    }
+}
+
+/// Prints every intermediate term, with the redex contracted at each step
+/// highlighted and followed by the name of the rule applied to it. Stops
+/// after `max_steps` steps.
+pub fn trace<T: Clone + Display + WriteTerm>(
+    term: &T,
+    max_steps: usize,
+    step: impl Fn(&T) -> Option<Step<T>>,
+) -> String {
+    let mut out = String::from("trace:\n");
+    let mut t = term.clone();
+    for _ in 0..max_steps {
+        let step = match step(&t) {
+            Some(step) => step,
+            None => break,
+        };
+        let highlighted = Highlight {
+            term: &t,
+            path: &step.path,
+        };
+        out.push_str(&format!(
+            "  {}  {}{}{}\n",
+            highlighted, CYAN, step.rule, RESET
+        ));
+        t = step.term;
+    }
+    out.push_str(&format!("  {}\n", t));
+    out
+}
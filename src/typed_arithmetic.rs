@@ -0,0 +1,223 @@
+use crate::derivation::Derivation;
+use crate::evaluator::parse_command;
+use crate::evaluator::Evaluator;
+use crate::untyped_arithmetic::describe;
+use crate::untyped_arithmetic::parse;
+use crate::untyped_arithmetic::tokens_error_to_string;
+use crate::untyped_arithmetic::Term;
+use crate::untyped_arithmetic::UntypedArithmetic;
+
+use std::fmt::{self, Display, Formatter};
+
+/// Arithmetic expressions with the types `Bool` and `Nat` (TAPL ch. 8).
+/// Terms are type checked before they are handed to the untyped evaluator.
+#[derive(Debug, Default)]
+pub struct TypedArithmetic {
+    untyped: UntypedArithmetic,
+    /// Whether the typing derivation is printed after the type.
+    typing: bool,
+}
+
+impl Evaluator for TypedArithmetic {
+    fn run(&mut self, input: &str) -> String {
+        if let Some((name, arg)) = parse_command(input) {
+            return self.untyped.run_command(name, arg);
+        }
+        let term = parse(input);
+        let mut output = String::new();
+        if term.is_err() {
+            output.push_str(term.err().unwrap().as_str());
+            return output;
+        }
+
+        let t = term.unwrap();
+        output.push_str(&describe(&t));
+
+        let typing = typing(&t, 0);
+        if typing.is_err() {
+            let err = type_error_to_string(typing.err().unwrap(), input);
+            output.push_str(err.as_str());
+            return output;
+        }
+        let (ty, derivation) = typing.unwrap();
+        output.push_str(format!("type: {}\n", ty).as_str());
+        if self.typing {
+            output.push_str("typing:\n");
+            output.push_str(&self.untyped.render(&derivation));
+        }
+
+        output.push_str(&self.untyped.evaluate(&t));
+        output
+    }
+    fn __debug__(&self) -> String {
+        format!("{:?}", self)
+    }
+    fn name(&self) -> String {
+        "Typed Arithmetic".to_string()
+    }
+
+    fn typing(&self) -> Option<bool> {
+        Some(self.typing)
+    }
+
+    fn set_typing(&mut self, typing: bool) {
+        self.typing = typing;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Type {
+    Bool,
+    Nat,
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Type::Bool => write!(f, "Bool"),
+            Type::Nat => write!(f, "Nat"),
+        }
+    }
+}
+
+/// Derives the type of `term`, whose first token is token number `offset`
+/// of the input, together with the derivation of `t : T`. Terms are written
+/// in prefix form, so every subterm covers a contiguous run of tokens and
+/// errors can point at the offending one.
+fn typing(term: &Term, offset: usize) -> Result<(Type, Derivation), TypeError> {
+    let (ty, rule, premises) = match term {
+        Term::True => (Type::Bool, "T-True", Vec::new()),
+        Term::False => (Type::Bool, "T-False", Vec::new()),
+        Term::Zero => (Type::Nat, "T-Zero", Vec::new()),
+        Term::If(t1, t2, t3) => {
+            let o1 = offset + 1;
+            let o2 = o1 + token_count(t1) + 1;
+            let o3 = o2 + token_count(t2) + 1;
+            let d1 = expect(t1, o1, Type::Bool)?;
+            let (ty2, d2) = typing(t2, o2)?;
+            let (ty3, d3) = typing(t3, o3)?;
+            if ty2 != ty3 {
+                let msg = format!("Branches have different types: {} and {}", ty2, ty3);
+                return Err(type_err(&msg, t3, o3));
+            }
+            (ty2, "T-If", vec![d1, d2, d3])
+        }
+        Term::Succ(t1) => (Type::Nat, "T-Succ", vec![expect(t1, offset + 1, Type::Nat)?]),
+        Term::Pred(t1) => (Type::Nat, "T-Pred", vec![expect(t1, offset + 1, Type::Nat)?]),
+        Term::IsZero(t1) => (Type::Bool, "T-IsZero", vec![expect(t1, offset + 1, Type::Nat)?]),
+    };
+    let conclusion = format!("{} : {}", term, ty);
+    Ok((ty, Derivation::new(rule, conclusion, premises)))
+}
+
+fn expect(term: &Term, offset: usize, expected: Type) -> Result<Derivation, TypeError> {
+    let (ty, d) = typing(term, offset)?;
+    if ty != expected {
+        let msg = format!("Expected a term of type {}, got {}", expected, ty);
+        return Err(type_err(&msg, term, offset));
+    }
+    Ok(d)
+}
+
+/// The number of input tokens `term` was parsed from.
+fn token_count(term: &Term) -> usize {
+    match term {
+        Term::True | Term::False | Term::Zero => 1,
+        Term::If(t1, t2, t3) => 3 + token_count(t1) + token_count(t2) + token_count(t3),
+        Term::Succ(t1) | Term::Pred(t1) | Term::IsZero(t1) => 1 + token_count(t1),
+    }
+}
+
+#[derive(Debug)]
+struct TypeError {
+    message: String,
+    offset: usize,
+    length: usize,
+}
+
+fn type_err(message: &str, term: &Term, offset: usize) -> TypeError {
+    TypeError {
+        message: message.to_string(),
+        offset,
+        length: token_count(term),
+    }
+}
+
+fn type_error_to_string(e: TypeError, input: &str) -> String {
+    tokens_error_to_string(&e.message, input, e.offset, e.length)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::evaluator::run_input;
+
+    #[test]
+    fn test_type_of() {
+        let cases = vec![
+            ("0", Ok(Type::Nat)),
+            ("iszero pred succ 0", Ok(Type::Bool)),
+            ("if iszero 0 then succ 0 else 0", Ok(Type::Nat)),
+            (
+                "succ true",
+                Err(("Expected a term of type Nat, got Bool", 1, 1)),
+            ),
+            (
+                "if 0 then true else false",
+                Err(("Expected a term of type Bool, got Nat", 1, 1)),
+            ),
+            (
+                "if true then 0 else iszero 0",
+                Err(("Branches have different types: Nat and Bool", 5, 2)),
+            ),
+            (
+                "pred if false then succ 0 else iszero 0",
+                Err(("Branches have different types: Nat and Bool", 7, 2)),
+            ),
+            ("succ if true then 0 else 0", Ok(Type::Nat)),
+            (
+                "iszero if true then false else true",
+                Err(("Expected a term of type Nat, got Bool", 1, 6)),
+            ),
+        ];
+
+        for (input, expected) in cases {
+            let t = parse(input).unwrap();
+            let result = typing(&t, 0)
+                .map(|(ty, _)| ty)
+                .map_err(|e| (e.message, e.offset, e.length));
+            let expected = expected.map_err(|(m, o, l)| (m.to_string(), o, l));
+            assert_eq!(result, expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_typing_derivation() {
+        let t = parse("if iszero 0 then succ 0 else 0").unwrap();
+        let (_, d) = typing(&t, 0).unwrap();
+        assert_eq!(
+            d.to_string(),
+            "if iszero 0 then succ 0 else 0 : Nat  (T-If)\n\
+             \x20 iszero 0 : Bool  (T-IsZero)\n\
+             \x20   0 : Nat  (T-Zero)\n\
+             \x20 succ 0 : Nat  (T-Succ)\n\
+             \x20   0 : Nat  (T-Zero)\n\
+             \x20 0 : Nat  (T-Zero)\n"
+        );
+    }
+
+    #[test]
+    fn test_typing_command() {
+        let mut evaluator = TypedArithmetic::default();
+        assert!(!run_input(&mut evaluator, "succ 0").contains("typing:"));
+        assert_eq!(run_input(&mut evaluator, ":typing"), "typing: on");
+        assert!(run_input(&mut evaluator, "succ 0").contains("typing:\nsucc 0 : Nat  (T-Succ)\n"));
+        assert_eq!(run_input(&mut evaluator, ":typing off"), "typing: off");
+        assert_eq!(
+            run_input(&mut evaluator, ":typing maybe"),
+            "Error: Expected 'on' or 'off', got 'maybe'"
+        );
+        let mut untyped = UntypedArithmetic::default();
+        assert_eq!(run_input(&mut untyped, ":typing"), "Error: Unknown command ':typing'");
+    }
+}
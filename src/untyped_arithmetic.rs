@@ -1,29 +1,99 @@
 use crate::colors::*;
 use std::collections::HashSet;
+use std::fmt::{self, Display, Formatter};
 
+use crate::derivation;
+use crate::derivation::Derivation;
+use crate::evaluator::parse_command;
+use crate::evaluator::parse_toggle;
 use crate::evaluator::Evaluator;
+use crate::trace;
+use crate::trace::child_path;
+use crate::trace::write_highlighted;
+use crate::trace::WriteTerm;
 
-#[derive(Debug)]
-pub struct UntypedArithmetic;
+#[derive(Debug, Default)]
+pub struct UntypedArithmetic {
+    trace: bool,
+    semantics: Semantics,
+    /// Whether `:derive` prints LaTeX instead of ASCII trees.
+    latex: bool,
+}
 
-impl Evaluator for UntypedArithmetic {
-    fn run(&self, input: &str) -> String {
-        let tokens = tokenize(input);
-        let term = parser(&tokens, 0);
-        let mut output = String::new();
-        if term.is_err() {
-            let err = parse_error_to_string(term.err().unwrap(), input);
-            output.push_str(err.as_str());
-            return output;
+/// The evaluation relation used by the REPL: the single step relation
+/// `t → t'` iterated to a value, or the big-step relation `t ⇓ v`
+/// (TAPL Ex. 3.5.17). Both produce the same results and the same errors.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum Semantics {
+    #[default]
+    SmallStep,
+    BigStep,
+}
+
+impl Semantics {
+    fn parse(name: &str) -> Option<Semantics> {
+        match name {
+            "small" | "small-step" => Some(Semantics::SmallStep),
+            "big" | "big-step" => Some(Semantics::BigStep),
+            _ => None,
         }
+    }
+}
+
+impl Display for Semantics {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Semantics::SmallStep => write!(f, "small"),
+            Semantics::BigStep => write!(f, "big"),
+        }
+    }
+}
 
-        let (t, _) = term.unwrap();
-        output.push_str(format!("{:?}\n", t).as_str());
-        output.push_str(format!("consts: {:?}\n", consts(&t)).as_str());
-        output.push_str(format!("size: {:?}\n", size(&t)).as_str());
-        output.push_str(format!("depth: {:?}\n", depth(&t)).as_str());
+impl UntypedArithmetic {
+    pub(crate) fn run_command(&mut self, name: &str, arg: &str) -> String {
+        match name {
+            "trace" => match parse_toggle(arg, self.trace) {
+                Some(trace) => {
+                    self.trace = trace;
+                    format!("trace: {}", if trace { "on" } else { "off" })
+                }
+                None => format!("Error: Expected 'on' or 'off', got '{}'", arg),
+            },
+            "semantics" if arg.is_empty() => format!("semantics: {}", self.semantics),
+            "semantics" => match Semantics::parse(arg) {
+                Some(semantics) => {
+                    self.semantics = semantics;
+                    format!("semantics: {}", semantics)
+                }
+                None => format!("Error: Unknown semantics '{}', expected 'small' or 'big'", arg),
+            },
+            "latex" => match parse_toggle(arg, self.latex) {
+                Some(latex) => {
+                    self.latex = latex;
+                    format!("latex: {}", if latex { "on" } else { "off" })
+                }
+                None => format!("Error: Expected 'on' or 'off', got '{}'", arg),
+            },
+            "derive" => match parse(arg) {
+                Ok(t) => self.derive(&t),
+                Err(err) => err,
+            },
+            _ => format!("Error: Unknown command ':{}'", name),
+        }
+    }
 
-        let result = eval(&t);
+    /// Evaluates a parsed term, following the current REPL settings. The
+    /// trace lists single steps, so it is only shown for small-step semantics.
+    pub(crate) fn evaluate(&self, t: &Term) -> String {
+        let mut output = String::new();
+        if self.trace && self.semantics == Semantics::SmallStep {
+            output.push_str(&trace(t));
+        }
+
+        let result = match self.semantics {
+            Semantics::SmallStep => eval(t),
+            Semantics::BigStep => big_step_eval(t),
+        };
         if result.is_err() {
             let err = eval_error_to_string(result.err().unwrap());
             output.push_str(err.as_str());
@@ -34,6 +104,56 @@ impl Evaluator for UntypedArithmetic {
         output.push_str(format!("{:?}\n", result.unwrap()).as_str());
         output
     }
+
+    /// Renders a derivation in the format chosen with `:latex`.
+    pub(crate) fn render(&self, d: &Derivation) -> String {
+        d.render(self.latex)
+    }
+
+    /// Prints the derivation of every step taken by the small-step
+    /// evaluator, or the single big-step derivation, of `t`.
+    fn derive(&self, t: &Term) -> String {
+        let mut output = String::new();
+        match self.semantics {
+            Semantics::SmallStep => {
+                let mut t = t.clone();
+                let mut n = 1;
+                while let Some(step) = little_step_eval(&t) {
+                    output.push_str(&format!("step {}:\n", n));
+                    output.push_str(&self.render(&step_derivation(&t, &step)));
+                    t = step.term;
+                    n += 1;
+                }
+                if !isval(&t) {
+                    output.push_str(&eval_error_to_string(stuck(&t)));
+                }
+            }
+            Semantics::BigStep => match big_step(t) {
+                Ok((_, d)) => output.push_str(&self.render(&d)),
+                Err(e) => output.push_str(&eval_error_to_string(e)),
+            },
+        }
+        output
+    }
+}
+
+impl Evaluator for UntypedArithmetic {
+    fn run(&mut self, input: &str) -> String {
+        if let Some((name, arg)) = parse_command(input) {
+            return self.run_command(name, arg);
+        }
+        let term = parse(input);
+        let mut output = String::new();
+        if term.is_err() {
+            output.push_str(term.err().unwrap().as_str());
+            return output;
+        }
+
+        let t = term.unwrap();
+        output.push_str(&describe(&t));
+        output.push_str(&self.evaluate(&t));
+        output
+    }
     fn __debug__(&self) -> String {
         format!("{:?}", self)
     }
@@ -46,6 +166,25 @@ fn tokenize(input: &str) -> Vec<&str> {
     input.split_whitespace().collect()
 }
 
+/// Tokenizes and parses `input`, rendering any error.
+pub(crate) fn parse(input: &str) -> Result<Term, String> {
+    let tokens = tokenize(input);
+    match parser(&tokens, 0) {
+        Ok((t, _)) => Ok(t),
+        Err(e) => Err(parse_error_to_string(e, input)),
+    }
+}
+
+/// The term's AST followed by its constants, size and depth.
+pub(crate) fn describe(t: &Term) -> String {
+    let mut output = String::new();
+    output.push_str(format!("{:?}\n", t).as_str());
+    output.push_str(format!("consts: {:?}\n", consts(t)).as_str());
+    output.push_str(format!("size: {:?}\n", size(t)).as_str());
+    output.push_str(format!("depth: {:?}\n", depth(t)).as_str());
+    output
+}
+
 fn consts(term: &Term) -> HashSet<&str> {
     let set = match term {
         Term::True => set("true"),
@@ -90,46 +229,144 @@ fn depth(term: &Term) -> usize {
 
 fn eval(term: &Term) -> Result<Term, EvalError> {
     let mut t = term.clone();
-    let mut prev = term.clone();
     loop {
         if isval(&t) {
-            return Ok(t.clone());
+            return Ok(t);
         }
-        t = little_step_eval(&t)?;
-        if prev == t {
-            return Err(eval_err("No rule applies", &t));
+        match little_step_eval(&t) {
+            Some(step) => t = step.term,
+            None => return Err(stuck(&t)),
         }
-        prev = t.clone();
     }
 }
 
-fn little_step_eval(term: &Term) -> Result<Term, EvalError> {
-    let t = match term {
-        Term::If(t1, t2, t3) => match **t1 {
-            Term::True => *t2.clone(),
-            Term::False => *t3.clone(),
-            _ => if_(little_step_eval(t1)?, *t2.clone(), *t3.clone()),
+/// Explains why no rule applies to `term`, which is not a value: somewhere
+/// inside it an `if` or a numeric operator is applied to a value of the
+/// wrong kind.
+fn stuck(term: &Term) -> EvalError {
+    match term {
+        Term::If(t1, _, _) if isval(t1) => EvalError::NotABoolean {
+            term: term.clone(),
+            value: *t1.clone(),
         },
-        Term::Succ(t1) => succ(little_step_eval(t1)?),
-        Term::Pred(t1) => {
-            let t = *t1.clone();
-            match t {
-                Term::Zero => Term::Zero,
-                Term::Succ(t2) if isnumericval(&t2) => *t2.clone(),
-                _ => pred(little_step_eval(t1)?),
+        Term::Succ(t1) | Term::Pred(t1) | Term::IsZero(t1) if isval(t1) => {
+            EvalError::NotANumber {
+                term: term.clone(),
+                value: *t1.clone(),
             }
         }
-        Term::IsZero(t1) => {
-            let t = *t1.clone();
-            match t {
-                Term::Zero => Term::True,
-                Term::Succ(t2) if isnumericval(&t2) => Term::False,
-                _ => iszero(little_step_eval(t1)?),
-            }
+        Term::If(t1, _, _) | Term::Succ(t1) | Term::Pred(t1) | Term::IsZero(t1) => stuck(t1),
+        Term::True | Term::False | Term::Zero => unreachable!("values are not stuck"),
+    }
+}
+
+/// The big-step relation `t ⇓ v` of TAPL Ex. 3.5.17. A subterm that
+/// evaluates to a value of the wrong kind produces the same error as
+/// `stuck` does for the corresponding small-step normal form.
+fn big_step_eval(term: &Term) -> Result<Term, EvalError> {
+    big_step(term).map(|(v, _)| v)
+}
+
+/// Evaluates `term` to a value together with the derivation of `t ⇓ v`.
+fn big_step(term: &Term) -> Result<(Term, Derivation), EvalError> {
+    let (v, rule, premises) = match term {
+        t if isval(t) => (t.clone(), "B-Value", Vec::new()),
+        Term::If(t1, t2, t3) => {
+            let (v1, d1) = big_step(t1)?;
+            let (rule, branch) = match v1 {
+                Term::True => ("B-IfTrue", t2),
+                Term::False => ("B-IfFalse", t3),
+                v => {
+                    return Err(EvalError::NotABoolean {
+                        term: if_(v.clone(), *t2.clone(), *t3.clone()),
+                        value: v,
+                    })
+                }
+            };
+            let (v, d2) = big_step(branch)?;
+            (v, rule, vec![d1, d2])
         }
-        Term::True | Term::False | Term::Zero => term.clone(),
+        Term::Succ(t1) => match big_step(t1)? {
+            (nv, d1) if isnumericval(&nv) => (succ(nv), "B-Succ", vec![d1]),
+            (v, _) => return Err(not_a_number(succ, v)),
+        },
+        Term::Pred(t1) => match big_step(t1)? {
+            (Term::Zero, d1) => (Term::Zero, "B-PredZero", vec![d1]),
+            (Term::Succ(nv), d1) => (*nv, "B-PredSucc", vec![d1]),
+            (v, _) => return Err(not_a_number(pred, v)),
+        },
+        Term::IsZero(t1) => match big_step(t1)? {
+            (Term::Zero, d1) => (Term::True, "B-IsZeroZero", vec![d1]),
+            (Term::Succ(_), d1) => (Term::False, "B-IsZeroSucc", vec![d1]),
+            (v, _) => return Err(not_a_number(iszero, v)),
+        },
+        Term::True | Term::False | Term::Zero => unreachable!(),
     };
-    Ok(t)
+    let conclusion = format!("{} ⇓ {}", term, v);
+    Ok((v, Derivation::new(rule, conclusion, premises)))
+}
+
+/// The derivation of a single step, built from the position of its redex.
+fn step_derivation(term: &Term, step: &Step) -> Derivation {
+    derivation::small_step(term, &step.term, &step.path, step.rule, child, congruence_rule)
+}
+
+fn child(term: &Term, index: usize) -> &Term {
+    match (term, index) {
+        (Term::If(t1, _, _), 0) => t1,
+        (Term::If(_, t2, _), 1) => t2,
+        (Term::If(_, _, t3), 2) => t3,
+        (Term::Succ(t1) | Term::Pred(t1) | Term::IsZero(t1), 0) => t1,
+        _ => panic!("{} has no subterm {}", term, index),
+    }
+}
+
+fn congruence_rule(term: &Term, _index: usize) -> &'static str {
+    match term {
+        Term::If(..) => "E-If",
+        Term::Succ(_) => "E-Succ",
+        Term::Pred(_) => "E-Pred",
+        Term::IsZero(_) => "E-IsZero",
+        Term::True | Term::False | Term::Zero => unreachable!("values do not step"),
+    }
+}
+
+fn not_a_number(op: fn(Term) -> Term, value: Term) -> EvalError {
+    EvalError::NotANumber {
+        term: op(value.clone()),
+        value,
+    }
+}
+
+type Step = trace::Step<Term>;
+
+fn little_step_eval(term: &Term) -> Option<Step> {
+    match term {
+        Term::If(t1, t2, t3) => match **t1 {
+            Term::True => Some(Step::new(*t2.clone(), "E-IfTrue")),
+            Term::False => Some(Step::new(*t3.clone(), "E-IfFalse")),
+            _ => little_step_eval(t1)
+                .map(|s| s.congruence(0, |t1| if_(t1, *t2.clone(), *t3.clone()))),
+        },
+        Term::Succ(t1) => little_step_eval(t1).map(|s| s.congruence(0, succ)),
+        Term::Pred(t1) => match &**t1 {
+            Term::Zero => Some(Step::new(Term::Zero, "E-PredZero")),
+            Term::Succ(t2) if isnumericval(t2) => Some(Step::new(*t2.clone(), "E-PredSucc")),
+            _ => little_step_eval(t1).map(|s| s.congruence(0, pred)),
+        },
+        Term::IsZero(t1) => match &**t1 {
+            Term::Zero => Some(Step::new(Term::True, "E-IsZeroZero")),
+            Term::Succ(t2) if isnumericval(t2) => Some(Step::new(Term::False, "E-IsZeroSucc")),
+            _ => little_step_eval(t1).map(|s| s.congruence(0, iszero)),
+        },
+        Term::True | Term::False | Term::Zero => None,
+    }
+}
+
+/// Prints every intermediate term, with the redex contracted at each step
+/// highlighted. Evaluation always terminates, so there is no step limit.
+fn trace(term: &Term) -> String {
+    trace::trace(term, usize::MAX, little_step_eval)
 }
 
 fn isval(term: &Term) -> bool {
@@ -148,17 +385,14 @@ fn isnumericval(term: &Term) -> bool {
     }
 }
 
-#[derive(Debug)]
-struct EvalError {
-    message: String,
-    term: Term,
-}
-
-fn eval_err(message: &str, term: &Term) -> EvalError {
-    EvalError {
-        message: message.to_string(),
-        term: term.clone(),
-    }
+/// A stuck term: `term` is the innermost subterm no rule applies to, after
+/// its argument was evaluated to `value`.
+#[derive(Debug, PartialEq)]
+enum EvalError {
+    /// The condition of an `if` is not `true` or `false`.
+    NotABoolean { term: Term, value: Term },
+    /// The argument of `succ`, `pred` or `iszero` is not a numeric value.
+    NotANumber { term: Term, value: Term },
 }
 
 fn set(t: &str) -> HashSet<&str> {
@@ -208,7 +442,7 @@ fn expect(tokens: &Vec<&str>, i: usize, expected: &str) -> Result<usize, ParseEr
 
 // AST
 #[derive(Debug, Clone, PartialEq)]
-enum Term {
+pub(crate) enum Term {
     True,
     False,
     If(Box<Term>, Box<Term>, Box<Term>),
@@ -231,6 +465,52 @@ fn iszero(t1: Term) -> Term {
     Term::IsZero(Box::new(t1))
 }
 
+impl Display for Term {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write_term(f, self, None)
+    }
+}
+
+impl WriteTerm for Term {
+    fn write_term(&self, f: &mut Formatter, path: Option<&[usize]>) -> fmt::Result {
+        write_term(f, self, path)
+    }
+}
+
+/// Writes `term` in the concrete syntax of the language, highlighting the
+/// subterm that `path` leads to, if any.
+fn write_term(f: &mut Formatter, term: &Term, path: Option<&[usize]>) -> fmt::Result {
+    if path == Some(&[]) {
+        return write_highlighted(f, |f| write_term(f, term, None));
+    }
+    let child = |i: usize| child_path(path, i);
+    match term {
+        Term::True => write!(f, "true"),
+        Term::False => write!(f, "false"),
+        Term::Zero => write!(f, "0"),
+        Term::If(t1, t2, t3) => {
+            write!(f, "if ")?;
+            write_term(f, t1, child(0))?;
+            write!(f, " then ")?;
+            write_term(f, t2, child(1))?;
+            write!(f, " else ")?;
+            write_term(f, t3, child(2))
+        }
+        Term::Succ(t1) => {
+            write!(f, "succ ")?;
+            write_term(f, t1, child(0))
+        }
+        Term::Pred(t1) => {
+            write!(f, "pred ")?;
+            write_term(f, t1, child(0))
+        }
+        Term::IsZero(t1) => {
+            write!(f, "iszero ")?;
+            write_term(f, t1, child(0))
+        }
+    }
+}
+
 #[derive(Debug)]
 struct ParseError {
     message: String,
@@ -240,20 +520,33 @@ struct ParseError {
 
 
 fn parse_error_to_string(e: ParseError, input: &str) -> String {
+    tokens_error_to_string(&e.message, input, e.offset, 1)
+}
+
+/// Renders an error message followed by the input, with the `length` tokens
+/// starting at token `offset` highlighted and underlined.
+pub(crate) fn tokens_error_to_string(message: &str, input: &str, offset: usize, length: usize) -> String {
     let mut out = String::new();
-    out.push_str(format!("Error: {}\n", e.message).as_str());
+    out.push_str(format!("Error: {}\n", message).as_str());
     let tokens = tokenize(input);
-    out.push_str(format!("{}{}", CYAN, tokens[..e.offset].join(" ")).as_str());
-    if e.offset > 0 {
+    let end = (offset + length).min(tokens.len());
+    let before = tokens[..offset].join(" ");
+    let marked = tokens[offset..end].join(" ");
+    let after = tokens[end..].join(" ");
+    out.push_str(format!("{}{}", CYAN, before).as_str());
+    if offset > 0 {
         out.push_str(" ");
     }
-    out.push_str(format!("{}{}{}\n", RED, tokens[e.offset], RESET).as_str());
-    for i in 0..e.offset {
-        let spaces = tokens[i].len();
-        out.push_str(format!("{:width$} ", "", width = spaces).as_str());
+    out.push_str(format!("{}{}", RED, marked).as_str());
+    if !after.is_empty() {
+        out.push_str(format!(" {}{}", CYAN, after).as_str());
     }
-    // ^^^ for the length of the token
-    out.push_str(format!("{}{}", RED, "^".repeat(tokens[e.offset].len())).as_str());
+    out.push_str(RESET);
+    out.push_str("\n");
+    let indent = before.chars().count() + if offset > 0 { 1 } else { 0 };
+    out.push_str(&" ".repeat(indent));
+    // ^^^ for the length of the marked tokens
+    out.push_str(format!("{}{}", RED, "^".repeat(marked.chars().count())).as_str());
     out.push_str(RESET);
     out.push_str("\n");
     out
@@ -267,8 +560,115 @@ fn err(message: &str, offset: usize) -> ParseError {
 }
 
 fn eval_error_to_string(e: EvalError) -> String {
+    let (expected, term, value) = match e {
+        EvalError::NotABoolean { term, value } => ("a boolean", term, value),
+        EvalError::NotANumber { term, value } => ("a numeric value", term, value),
+    };
     let mut out = String::new();
-    out.push_str(format!("Error: {}\n", e.message).as_str());
-    out.push_str(format!("{:?}\n", e.term).as_str());
+    out.push_str(format!("Error: Stuck term, expected {} but got {}\n", expected, value).as_str());
+    out.push_str(format!("{}\n", term).as_str());
     out
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Every term of depth at most `depth`.
+    fn terms(depth: usize) -> Vec<Term> {
+        let mut terms = vec![Term::True, Term::False, Term::Zero];
+        if depth <= 1 {
+            return terms;
+        }
+        let smaller = self::terms(depth - 1);
+        for t in &smaller {
+            terms.push(succ(t.clone()));
+            terms.push(pred(t.clone()));
+            terms.push(iszero(t.clone()));
+        }
+        for t1 in &smaller {
+            for t2 in &smaller {
+                for t3 in &smaller {
+                    terms.push(if_(t1.clone(), t2.clone(), t3.clone()));
+                }
+            }
+        }
+        terms
+    }
+
+    #[test]
+    fn test_big_step_agrees_with_small_step() {
+        for t in terms(3) {
+            assert_eq!(big_step_eval(&t), eval(&t), "{}", t);
+        }
+    }
+
+    #[test]
+    fn test_derivations() {
+        let t = parse("if iszero pred succ 0 then 0 else succ 0").unwrap();
+        let step = little_step_eval(&t).unwrap();
+        assert_eq!(
+            step_derivation(&t, &step).to_string(),
+            "if iszero pred succ 0 then 0 else succ 0 → if iszero 0 then 0 else succ 0  (E-If)\n\
+             \x20 iszero pred succ 0 → iszero 0  (E-IsZero)\n\
+             \x20   pred succ 0 → 0  (E-PredSucc)\n"
+        );
+
+        let (_, d) = big_step(&t).unwrap();
+        assert_eq!(
+            d.to_string(),
+            "if iszero pred succ 0 then 0 else succ 0 ⇓ 0  (B-IfTrue)\n\
+             \x20 iszero pred succ 0 ⇓ true  (B-IsZeroZero)\n\
+             \x20   pred succ 0 ⇓ 0  (B-PredSucc)\n\
+             \x20     succ 0 ⇓ succ 0  (B-Value)\n\
+             \x20 0 ⇓ 0  (B-Value)\n"
+        );
+    }
+
+    #[test]
+    fn test_trace() {
+        let t = parse("if iszero pred succ 0 then succ 0 else 0").unwrap();
+        assert_eq!(
+            trace(&t),
+            format!(
+                "trace:\n\
+                 \x20 if iszero {RED}pred succ 0{RESET} then succ 0 else 0  {CYAN}E-PredSucc{RESET}\n\
+                 \x20 if {RED}iszero 0{RESET} then succ 0 else 0  {CYAN}E-IsZeroZero{RESET}\n\
+                 \x20 {RED}if true then succ 0 else 0{RESET}  {CYAN}E-IfTrue{RESET}\n\
+                 \x20 succ 0\n"
+            )
+        );
+    }
+
+    #[test]
+    fn test_stuck_terms() {
+        let cases = vec![
+            (
+                "pred true",
+                EvalError::NotANumber {
+                    term: pred(Term::True),
+                    value: Term::True,
+                },
+            ),
+            (
+                "succ if iszero 0 then false else 0",
+                EvalError::NotANumber {
+                    term: succ(Term::False),
+                    value: Term::False,
+                },
+            ),
+            (
+                "if pred succ 0 then true else false",
+                EvalError::NotABoolean {
+                    term: if_(Term::Zero, Term::True, Term::False),
+                    value: Term::Zero,
+                },
+            ),
+        ];
+
+        for (input, expected) in cases {
+            let t = parse(input).unwrap();
+            assert_eq!(eval(&t), Err(expected), "{}", input);
+        }
+    }
+}
@@ -1,3 +1,10 @@
+mod nameless;
+mod prelude;
+
+use crate::derivation;
+use crate::derivation::Derivation;
+use crate::evaluator::parse_command;
+use crate::evaluator::parse_toggle;
 use crate::evaluator::Evaluator;
 use crate::parsing_utils::tok_err;
 use crate::parsing_utils::tokenize_error_to_string;
@@ -8,15 +15,230 @@ use crate::parsing_utils::TokenizeError;
 use crate::parsing_utils::ParseError;
 use crate::parsing_utils::parse_err;
 use crate::parsing_utils::parse_error_to_string;
+use crate::trace;
+use crate::trace::child_path;
+use crate::trace::write_highlighted;
+use crate::trace::WriteTerm;
 
-
+use std::collections::HashSet;
 use std::fmt::{self, Display, Formatter};
 
-#[derive(Debug)]
-pub struct UntypedLambdaCalculus;
+#[derive(Debug, Default)]
+pub struct UntypedLambdaCalculus {
+    strategy: Strategy,
+    trace: bool,
+    /// Whether `:derive` prints LaTeX instead of ASCII trees.
+    latex: bool,
+    /// Top-level definitions, in the order they were made. Their bodies are
+    /// already expanded, so they only mention names that were undefined at
+    /// the time.
+    env: Vec<(String, Term)>,
+    /// Whether the Church encoding prelude was loaded. Enables the `cN`
+    /// numerals and reading results back as numerals and booleans.
+    prelude_loaded: bool,
+}
+
+impl UntypedLambdaCalculus {
+    fn define(&mut self, name: String, term: Term) {
+        match self.env.iter_mut().find(|(n, _)| *n == name) {
+            Some(def) => def.1 = term,
+            None => self.env.push((name, term)),
+        }
+    }
+
+    /// Replaces the free identifiers of `term` that have a definition by
+    /// their definitions. Later definitions are substituted first, so the
+    /// free names of an earlier definition are not picked up by them. Fails
+    /// on a numeral name above `prelude::MAX_NUMERAL`.
+    fn expand(&self, term: &Term) -> Result<Term, EvalError> {
+        let mut t = term.clone();
+        for (name, def) in self.env.iter().rev() {
+            if free_vars(&t).contains(name) {
+                t = substitute(&t, name, def);
+            }
+        }
+        if self.prelude_loaded {
+            for name in free_vars(&t) {
+                if let Some(n) = prelude::numeral_name(&name) {
+                    if n > prelude::MAX_NUMERAL {
+                        let msg = format!(
+                            "Numeral '{}' is larger than the largest numeral c{}",
+                            name,
+                            prelude::MAX_NUMERAL
+                        );
+                        return Err(eval_err(&msg, term));
+                    }
+                    t = substitute(&t, &name, &prelude::numeral(n));
+                }
+            }
+        }
+        Ok(t)
+    }
+
+    fn load_prelude(&mut self) -> String {
+        let mut names = Vec::new();
+        for (name, term) in prelude::definitions() {
+            let term = match self.expand(&term) {
+                Ok(term) => term,
+                Err(e) => return eval_error_to_string(e),
+            };
+            self.define(name.to_string(), term);
+            names.push(name);
+        }
+        self.prelude_loaded = true;
+        format!("loaded: {} c0 c1 c2 ...", names.join(" "))
+    }
+
+    fn run_command(&mut self, name: &str, arg: &str) -> String {
+        match name {
+            "strategy" if arg.is_empty() => {
+                let mut out = format!("strategy: {}\navailable:", self.strategy);
+                for strategy in Strategy::ALL {
+                    out.push_str(&format!(" {}", strategy));
+                }
+                out
+            }
+            "strategy" => match Strategy::parse(arg) {
+                Some(strategy) => {
+                    self.strategy = strategy;
+                    format!("strategy: {}", self.strategy)
+                }
+                None => format!("Error: Unknown strategy '{}'", arg),
+            },
+            "trace" => match parse_toggle(arg, self.trace) {
+                Some(trace) => {
+                    self.trace = trace;
+                    format!("trace: {}", if trace { "on" } else { "off" })
+                }
+                None => format!("Error: Expected 'on' or 'off', got '{}'", arg),
+            },
+            "latex" => match parse_toggle(arg, self.latex) {
+                Some(latex) => {
+                    self.latex = latex;
+                    format!("latex: {}", if latex { "on" } else { "off" })
+                }
+                None => format!("Error: Expected 'on' or 'off', got '{}'", arg),
+            },
+            "derive" => match self.parse_expanded(arg) {
+                Ok(term) => self.derive(&term),
+                Err(err) => err,
+            },
+            "prelude" => self.load_prelude(),
+            "env" => {
+                let defs: Vec<String> = self
+                    .env
+                    .iter()
+                    .map(|(name, term)| format!("{} = {}", name, term))
+                    .collect();
+                defs.join("\n")
+            }
+            "db" => match self.parse_expanded(arg) {
+                Ok(term) => {
+                    let ctx = nameless::free_vars_context(&[&term]);
+                    let nameless = nameless::removenames(&ctx, &term).unwrap();
+                    let names: Vec<String> = ctx
+                        .iter()
+                        .rev()
+                        .enumerate()
+                        .map(|(i, x)| format!("{} ↦ {}", x, i))
+                        .collect();
+                    let mut out = format!("Γ = {}\nde Bruijn: {}\n", names.join(", "), nameless);
+                    let mut t = nameless;
+                    for _ in 0..MAX_STEPS {
+                        match nameless::normal_order_step(&t) {
+                            Some(next) => t = next,
+                            None => {
+                                out.push_str(&format!("normal form: {}\n", t));
+                                let restored = nameless::restorenames(&ctx, &t).unwrap();
+                                out.push_str(&format!("restored: {}", restored));
+                                return out;
+                            }
+                        }
+                    }
+                    let msg = format!("Evaluation did not terminate within {} steps", MAX_STEPS);
+                    let restored = nameless::restorenames(&ctx, &t).unwrap();
+                    out.push_str(&eval_error_to_string(eval_err(&msg, &restored)));
+                    out
+                }
+                Err(err) => err,
+            },
+            "eq" | "beq" => match parse_equation_str(arg) {
+                Ok((t1, t2)) => match (self.expand(&t1), self.expand(&t2)) {
+                    (Ok(t1), Ok(t2)) => equivalence(&t1, &t2, name == "beq"),
+                    (Err(e), _) | (_, Err(e)) => eval_error_to_string(e),
+                },
+                Err(err) => err,
+            },
+            _ => format!("Error: Unknown command ':{}'", name),
+        }
+    }
+
+    /// Parses a term given as a command argument and expands its
+    /// definitions.
+    fn parse_expanded(&self, input: &str) -> Result<Term, String> {
+        let term = parse_str(input)?;
+        self.expand(&term).map_err(eval_error_to_string)
+    }
+
+    /// Prints the derivation of every reduction step of `term` under the
+    /// current strategy.
+    fn derive(&self, term: &Term) -> String {
+        let mut out = String::new();
+        let mut t = term.clone();
+        for n in 1..=MAX_STEPS {
+            let step = match reduce_step(&t, self.strategy) {
+                Some(step) => step,
+                None => return out,
+            };
+            let d = step_derivation(&t, &step);
+            out.push_str(&format!("step {}:\n", n));
+            out.push_str(&d.render(self.latex));
+            t = step.term;
+        }
+        let msg = format!("Evaluation did not terminate within {} steps", MAX_STEPS);
+        out.push_str(&eval_error_to_string(eval_err(&msg, &t)));
+        out
+    }
+}
+
+/// Reports whether `t1` and `t2` are alpha-equivalent and, if `beta` is set,
+/// whether they are beta-equivalent, i.e. have alpha-equivalent normal forms.
+fn equivalence(t1: &Term, t2: &Term, beta: bool) -> String {
+    let yes_no = |b: bool| if b { "yes" } else { "no" };
+    let mut out = format!("alpha-equivalent: {}", yes_no(nameless::alpha_equivalent(t1, t2)));
+    if !beta {
+        return out;
+    }
+    out.push_str("\nbeta-equivalent: ");
+    let n1 = eval(t1, Strategy::NormalOrder);
+    let n2 = eval(t2, Strategy::NormalOrder);
+    match (n1, n2) {
+        (Ok(n1), Ok(n2)) => out.push_str(yes_no(nameless::alpha_equivalent(&n1, &n2))),
+        (Err(e), _) | (_, Err(e)) => {
+            out.push_str(&format!("unknown\n{}", eval_error_to_string(e)));
+        }
+    }
+    out
+}
+
+/// Tokenizes and parses a term given as a command argument.
+fn parse_str(input: &str) -> Result<Term, String> {
+    let tokens = tokenize(input).map_err(|e| tokenize_error_to_string(e, input))?;
+    parse(&tokens).map_err(|e| parse_error_to_string(e, input))
+}
+
+/// Tokenizes and parses the two terms, separated by `=`, that `:eq` and
+/// `:beq` compare.
+fn parse_equation_str(input: &str) -> Result<(Term, Term), String> {
+    let tokens = tokenize(input).map_err(|e| tokenize_error_to_string(e, input))?;
+    parse_equation(&tokens).map_err(|e| parse_error_to_string(e, input))
+}
 
 impl Evaluator for UntypedLambdaCalculus {
-    fn run(&self, input: &str) -> String {
+    fn run(&mut self, input: &str) -> String {
+        if let Some((name, arg)) = parse_command(input) {
+            return self.run_command(name, arg);
+        }
         let mut out = String::new();
         out.push_str("input: ");
         out.push_str(input);
@@ -34,15 +256,61 @@ impl Evaluator for UntypedLambdaCalculus {
         }
         out.push_str("]\n");
 
-        let term = parse(&tokens);
-        if term.is_err() {
-            let err = parse_error_to_string(term.err().unwrap(), input);
+        let input_item = parse_input(&tokens);
+        if input_item.is_err() {
+            let err = parse_error_to_string(input_item.err().unwrap(), input);
             out.push_str(err.as_str());
             return out;
         }
-        let term = term.unwrap();
+        let term = match input_item.unwrap() {
+            Input::Definition(name, term) => {
+                let term = match self.expand(&term) {
+                    Ok(term) => term,
+                    Err(e) => {
+                        out.push_str(&eval_error_to_string(e));
+                        return out;
+                    }
+                };
+                out.push_str(&format!("{} = {}\n", name, term));
+                self.define(name, term);
+                return out;
+            }
+            Input::Term(term) => term,
+        };
         out.push_str("parsed: ");
         out.push_str(&format!("{}\n", term));
+
+        let expanded = match self.expand(&term) {
+            Ok(expanded) => expanded,
+            Err(e) => {
+                out.push_str(&eval_error_to_string(e));
+                return out;
+            }
+        };
+        if expanded != term {
+            out.push_str("expanded: ");
+            out.push_str(&format!("{}\n", expanded));
+        }
+        let term = expanded;
+
+        if self.trace {
+            out.push_str(&trace(&term, self.strategy));
+        }
+
+        let result = eval(&term, self.strategy);
+        if result.is_err() {
+            let err = eval_error_to_string(result.err().unwrap());
+            out.push_str(err.as_str());
+            return out;
+        }
+        let result = result.unwrap();
+        match prelude::read_back(&result) {
+            Some(value) if self.prelude_loaded => {
+                out.push_str(&format!("result: {}\n", value));
+                out.push_str(&format!("normal form: {}\n", result));
+            }
+            _ => out.push_str(&format!("result: {}\n", result)),
+        }
         out
     }
     fn __debug__(&self) -> String {
@@ -53,7 +321,7 @@ impl Evaluator for UntypedLambdaCalculus {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 enum Term {
     Var(String),
     Abs(String, Box<Term>),
@@ -62,14 +330,62 @@ enum Term {
 
 impl Display for Term {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        match self {
-            Term::Var(id) => write!(f, "{}", id),
-            Term::Abs(id, exp) => write!(f, "λ{}.{}", id, exp),
-            Term::App(t1, t2) => write!(f, "({} {})", t1, t2),
+        write_term(f, self, None, true)
+    }
+}
+
+impl WriteTerm for Term {
+    fn write_term(&self, f: &mut Formatter, path: Option<&[usize]>) -> fmt::Result {
+        write_term(f, self, path, true)
+    }
+}
+
+/// Writes `term`, highlighting the subterm that `path` leads to, if any.
+///
+/// Only the parentheses needed to parse the output back are written:
+/// application is left-associative and an abstraction extends as far right
+/// as possible. `tail` is set when nothing follows `term` up to the end of
+/// the enclosing parentheses, in which case an abstraction needs none.
+fn write_term(f: &mut Formatter, term: &Term, path: Option<&[usize]>, tail: bool) -> fmt::Result {
+    if path == Some(&[]) {
+        return write_highlighted(f, |f| write_term(f, term, None, tail));
+    }
+    let child = |i: usize| child_path(path, i);
+    match term {
+        Term::Var(id) => write!(f, "{}", id),
+        Term::Abs(id, exp) => {
+            write!(f, "λ{}.", id)?;
+            write_term(f, exp, child(0), tail)
+        }
+        Term::App(t1, t2) => {
+            let parens = matches!(**t1, Term::Abs(_, _));
+            write_subterm(f, t1, child(0), parens, false)?;
+            write!(f, " ")?;
+            let parens = match **t2 {
+                Term::Var(_) => false,
+                Term::Abs(_, _) => !tail,
+                Term::App(_, _) => true,
+            };
+            write_subterm(f, t2, child(1), parens, tail)
         }
     }
 }
 
+fn write_subterm(
+    f: &mut Formatter,
+    term: &Term,
+    path: Option<&[usize]>,
+    parens: bool,
+    tail: bool,
+) -> fmt::Result {
+    if !parens {
+        return write_term(f, term, path, tail);
+    }
+    write!(f, "(")?;
+    write_term(f, term, path, true)?;
+    write!(f, ")")
+}
+
 fn app(term: Term, param: Term) -> Term {
     Term::App(Box::new(term), Box::new(param))
 }
@@ -78,12 +394,269 @@ fn abs(id: String, exp: Term) -> Term {
     Term::Abs(id, Box::new(exp))
 }
 
+/// Upper bound on reduction steps, so that terms without a normal form
+/// (e.g. `(λx.x x) (λx.x x)`) do not hang the REPL.
+const MAX_STEPS: usize = 1000;
+
+/// Reduction strategies from TAPL §5.1.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+enum Strategy {
+    #[default]
+    NormalOrder,
+    ApplicativeOrder,
+    CallByName,
+    CallByValue,
+}
+
+impl Strategy {
+    const ALL: [Strategy; 4] = [
+        Strategy::NormalOrder,
+        Strategy::ApplicativeOrder,
+        Strategy::CallByName,
+        Strategy::CallByValue,
+    ];
+
+    fn parse(name: &str) -> Option<Strategy> {
+        match name {
+            "normal" | "normal-order" => Some(Strategy::NormalOrder),
+            "applicative" | "applicative-order" => Some(Strategy::ApplicativeOrder),
+            "cbn" | "call-by-name" => Some(Strategy::CallByName),
+            "cbv" | "call-by-value" => Some(Strategy::CallByValue),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Strategy {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Strategy::NormalOrder => write!(f, "normal"),
+            Strategy::ApplicativeOrder => write!(f, "applicative"),
+            Strategy::CallByName => write!(f, "cbn"),
+            Strategy::CallByValue => write!(f, "cbv"),
+        }
+    }
+}
+
+fn eval(term: &Term, strategy: Strategy) -> Result<Term, EvalError> {
+    let mut t = term.clone();
+    for _ in 0..MAX_STEPS {
+        match reduce_step(&t, strategy) {
+            Some(step) => t = step.term,
+            None => return Ok(t),
+        }
+    }
+    let msg = format!("Evaluation did not terminate within {} steps", MAX_STEPS);
+    Err(eval_err(&msg, &t))
+}
+
+/// Prints every intermediate term, with the redex contracted at each step
+/// highlighted.
+fn trace(term: &Term, strategy: Strategy) -> String {
+    trace::trace(term, MAX_STEPS, |t| reduce_step(t, strategy))
+}
+
+type Step = trace::Step<Term>;
+
+/// The derivation of a single step, built from the position of its redex.
+/// Reducing under an abstraction is justified by E-Abs.
+fn step_derivation(term: &Term, step: &Step) -> Derivation {
+    derivation::small_step(term, &step.term, &step.path, step.rule, child, congruence_rule)
+}
+
+fn child(term: &Term, index: usize) -> &Term {
+    match (term, index) {
+        (Term::Abs(_, body), 0) => body,
+        (Term::App(t1, _), 0) => t1,
+        (Term::App(_, t2), 1) => t2,
+        _ => panic!("{} has no subterm {}", term, index),
+    }
+}
+
+fn congruence_rule(term: &Term, index: usize) -> &'static str {
+    match (term, index) {
+        (Term::App(..), 0) => "E-App1",
+        (Term::App(..), _) => "E-App2",
+        (Term::Abs(..), _) => "E-Abs",
+        (Term::Var(_), _) => unreachable!("variables do not step"),
+    }
+}
+
+/// Contracts the redex `(λid.body) arg`.
+fn beta(id: &str, body: &Term, arg: &Term) -> Step {
+    Step::new(substitute(body, id, arg), "E-AppAbs")
+}
+
+fn reduce_step(term: &Term, strategy: Strategy) -> Option<Step> {
+    match strategy {
+        Strategy::NormalOrder => normal_order_step(term),
+        Strategy::ApplicativeOrder => applicative_order_step(term),
+        Strategy::CallByName => call_by_name_step(term),
+        Strategy::CallByValue => call_by_value_step(term),
+    }
+}
+
+/// The leftmost, outermost redex is contracted first, reducing under
+/// abstractions as well. Reaches the normal form whenever there is one.
+fn normal_order_step(term: &Term) -> Option<Step> {
+    match term {
+        Term::Var(_) => None,
+        Term::Abs(id, body) => {
+            normal_order_step(body).map(|s| s.congruence(0, |body| abs(id.clone(), body)))
+        }
+        Term::App(t1, t2) => match &**t1 {
+            Term::Abs(id, body) => Some(beta(id, body, t2)),
+            _ => normal_order_step(t1)
+                .map(|s| s.congruence(0, |t1| app(t1, *t2.clone())))
+                .or_else(|| {
+                    normal_order_step(t2).map(|s| s.congruence(1, |t2| app(*t1.clone(), t2)))
+                }),
+        },
+    }
+}
+
+/// The leftmost, innermost redex is contracted first: both the function and
+/// its argument are normalized before the application itself is reduced.
+fn applicative_order_step(term: &Term) -> Option<Step> {
+    match term {
+        Term::Var(_) => None,
+        Term::Abs(id, body) => {
+            applicative_order_step(body).map(|s| s.congruence(0, |body| abs(id.clone(), body)))
+        }
+        Term::App(t1, t2) => {
+            if let Some(s) = applicative_order_step(t1) {
+                return Some(s.congruence(0, |t1| app(t1, *t2.clone())));
+            }
+            if let Some(s) = applicative_order_step(t2) {
+                return Some(s.congruence(1, |t2| app(*t1.clone(), t2)));
+            }
+            match &**t1 {
+                Term::Abs(id, body) => Some(beta(id, body, t2)),
+                _ => None,
+            }
+        }
+    }
+}
+
+/// Like normal order, but no reductions happen inside abstractions and
+/// arguments are passed unevaluated.
+fn call_by_name_step(term: &Term) -> Option<Step> {
+    match term {
+        Term::Var(_) | Term::Abs(_, _) => None,
+        Term::App(t1, t2) => match &**t1 {
+            Term::Abs(id, body) => Some(beta(id, body, t2)),
+            _ => call_by_name_step(t1).map(|s| s.congruence(0, |t1| app(t1, *t2.clone()))),
+        },
+    }
+}
+
+/// Only outermost redexes whose argument is already a value are contracted,
+/// and no reductions happen inside abstractions (E-App1, E-App2, E-AppAbs).
+fn call_by_value_step(term: &Term) -> Option<Step> {
+    match term {
+        Term::Var(_) | Term::Abs(_, _) => None,
+        Term::App(t1, t2) => {
+            if let Some(s) = call_by_value_step(t1) {
+                return Some(s.congruence(0, |t1| app(t1, *t2.clone())));
+            }
+            if !isval(t1) {
+                return None;
+            }
+            if let Some(s) = call_by_value_step(t2) {
+                return Some(s.congruence(1, |t2| app(*t1.clone(), t2)));
+            }
+            match &**t1 {
+                Term::Abs(id, body) if isval(t2) => Some(beta(id, body, t2)),
+                _ => None,
+            }
+        }
+    }
+}
+
+/// Abstractions are values. Variables are treated as values too, so that
+/// open terms such as `(λx.x) y` still reduce.
+fn isval(term: &Term) -> bool {
+    matches!(term, Term::Abs(_, _) | Term::Var(_))
+}
+
+/// Capture-avoiding substitution `[id ↦ value] term`.
+fn substitute(term: &Term, id: &str, value: &Term) -> Term {
+    match term {
+        Term::Var(x) if x == id => value.clone(),
+        Term::Var(_) => term.clone(),
+        Term::Abs(x, _) if x == id => term.clone(),
+        Term::Abs(x, body) => {
+            let fv = free_vars(value);
+            if !fv.contains(x) {
+                return abs(x.clone(), substitute(body, id, value));
+            }
+            // rename the bound variable so it does not capture a free
+            // variable of `value`
+            let mut avoid = fv;
+            avoid.extend(free_vars(body));
+            avoid.insert(id.to_string());
+            let fresh = fresh_name(x, &avoid);
+            let body = substitute(body, x, &Term::Var(fresh.clone()));
+            abs(fresh, substitute(&body, id, value))
+        }
+        Term::App(t1, t2) => app(substitute(t1, id, value), substitute(t2, id, value)),
+    }
+}
+
+fn free_vars(term: &Term) -> HashSet<String> {
+    match term {
+        Term::Var(x) => HashSet::from([x.clone()]),
+        Term::Abs(x, body) => {
+            let mut fv = free_vars(body);
+            fv.remove(x);
+            fv
+        }
+        Term::App(t1, t2) => {
+            let mut fv = free_vars(t1);
+            fv.extend(free_vars(t2));
+            fv
+        }
+    }
+}
+
+/// Returns `id` followed by the smallest number that is not in `avoid`.
+fn fresh_name(id: &str, avoid: &HashSet<String>) -> String {
+    let base = id.trim_end_matches(|c: char| c.is_ascii_digit());
+    let base = if base.is_empty() { id } else { base };
+    (1..)
+        .map(|i| format!("{}{}", base, i))
+        .find(|name| !avoid.contains(name))
+        .unwrap()
+}
+
+#[derive(Debug)]
+struct EvalError {
+    message: String,
+    term: Term,
+}
+
+fn eval_err(message: &str, term: &Term) -> EvalError {
+    EvalError {
+        message: message.to_string(),
+        term: term.clone(),
+    }
+}
+
+fn eval_error_to_string(e: EvalError) -> String {
+    let mut out = String::new();
+    out.push_str(format!("Error: {}\n", e.message).as_str());
+    out.push_str(format!("{}\n", e.term).as_str());
+    out
+}
+
 #[derive(Debug, PartialEq)]
 enum Token {
     Lambda,
     Dot,
     LParen,
     RParen,
+    Equals,
+    Let,
     Identifier(String),
 }
 
@@ -94,13 +667,13 @@ impl Display for Token {
             Token::Dot => write!(f, "."),
             Token::LParen => write!(f, "("),
             Token::RParen => write!(f, ")"),
+            Token::Equals => write!(f, "="),
+            Token::Let => write!(f, "let"),
             Token::Identifier(id) => write!(f, "{}", id),
         }
     }
 }
 
-
-
 fn tokenize(input: &str) -> Result<Vec<Spanned<Token>>, TokenizeError> {
     let mut it = InputIterator::new(input);
     while let Some(&c) = it.peek() {
@@ -109,6 +682,7 @@ fn tokenize(input: &str) -> Result<Vec<Spanned<Token>>, TokenizeError> {
             '.' => it.next_and_push(Token::Dot, 1),
             '(' => it.next_and_push(Token::LParen, 1),
             ')' => it.next_and_push(Token::RParen, 1),
+            '=' => it.next_and_push(Token::Equals, 1),
             c if c.is_whitespace() => {
                 it.next();
             }
@@ -123,7 +697,11 @@ fn tokenize(input: &str) -> Result<Vec<Spanned<Token>>, TokenizeError> {
                     }
                 }
                 let len = identifier.len();
-                it.push(Token::Identifier(identifier), len);
+                if identifier == "let" {
+                    it.push(Token::Let, len);
+                } else {
+                    it.push(Token::Identifier(identifier), len);
+                }
             }
             _ => {
                 it.next();
@@ -134,10 +712,50 @@ fn tokenize(input: &str) -> Result<Vec<Spanned<Token>>, TokenizeError> {
     Ok(it.tokens)
 }
 
+/// A line of REPL input: either a top-level definition or a term.
+enum Input {
+    Definition(String, Term),
+    Term(Term),
+}
+
+/// input ::= "let"? identifier "=" term | term
+fn parse_input(tokens: &[Spanned<Token>]) -> Result<Input, ParseError> {
+    let mut it = tokens.iter().peekable();
+    let is_definition = match tokens {
+        [t, ..] if t.item == Token::Let => {
+            it.next();
+            true
+        }
+        [t1, t2, ..] => matches!(t1.item, Token::Identifier(_)) && t2.item == Token::Equals,
+        _ => false,
+    };
+    if !is_definition {
+        let term = parse_term(&mut it)?;
+        expect_end(&mut it)?;
+        return Ok(Input::Term(term));
+    }
+    let id = expect_identifier(&mut it)?;
+    expect_token(&mut it, Token::Equals)?;
+    let term = parse_term(&mut it)?;
+    expect_end(&mut it)?;
+    Ok(Input::Definition(id, term))
+}
 
 fn parse(tokens: &Vec<Spanned<Token>>) -> Result<Term, ParseError> {
     let mut it = tokens.iter().peekable();
-    parse_term(&mut it)
+    let term = parse_term(&mut it)?;
+    expect_end(&mut it)?;
+    Ok(term)
+}
+
+/// equation ::= term "=" term
+fn parse_equation(tokens: &[Spanned<Token>]) -> Result<(Term, Term), ParseError> {
+    let mut it = tokens.iter().peekable();
+    let t1 = parse_term(&mut it)?;
+    expect_token(&mut it, Token::Equals)?;
+    let t2 = parse_term(&mut it)?;
+    expect_end(&mut it)?;
+    Ok((t1, t2))
 }
 
 enum Precedence {
@@ -146,13 +764,16 @@ enum Precedence {
     Lambda,
 }
 
+// term        ::= "λ" identifier "." term | application
+// application ::= atom+ ("λ" identifier "." term)?
+// atom        ::= identifier | "(" term ")"
+
 fn parse_term(it: &mut std::iter::Peekable<std::slice::Iter<Spanned<Token>>>) -> Result<Term, ParseError> {
     match it.peek() {
         None => Err(parse_err("Unexpected end of input", Span::new(0, 0))),
         Some(t) => match t.item {
-            Token::Identifier(_) => parse_application(it),
             Token::Lambda => parse_abstraction(it),
-            _ => Err(parse_err("Unexpected token", t.span)),
+            _ => parse_application(it),
         },
     }
 }
@@ -170,13 +791,11 @@ fn parse_abstraction(
 fn parse_application(
     it: &mut std::iter::Peekable<std::slice::Iter<Spanned<Token>>>,
 ) -> Result<Term, ParseError> {
-    let id = expect_identifier(it)?;
-    let mut exp = Term::Var(id);
+    let mut exp = parse_atom(it)?;
     while let Some(t) = it.peek() {
         match t.item {
-            Token::Identifier(_) => {
-                let id = expect_identifier(it)?;
-                let term = Term::Var(id);
+            Token::Identifier(_) | Token::LParen => {
+                let term = parse_atom(it)?;
                 exp = app(exp, term);
             }
             Token::Lambda => {
@@ -190,6 +809,33 @@ fn parse_application(
     Ok(exp)
 }
 
+fn parse_atom(
+    it: &mut std::iter::Peekable<std::slice::Iter<Spanned<Token>>>,
+) -> Result<Term, ParseError> {
+    match it.peek() {
+        None => Err(parse_err("Unexpected end of input", Span::new(0, 0))),
+        Some(t) => match t.item {
+            Token::Identifier(_) => Ok(Term::Var(expect_identifier(it)?)),
+            Token::LParen => {
+                expect_token(it, Token::LParen)?;
+                let term = parse_term(it)?;
+                expect_token(it, Token::RParen)?;
+                Ok(term)
+            }
+            _ => Err(parse_err(&format!("Unexpected token '{}'", t.item), t.span)),
+        },
+    }
+}
+
+fn expect_end(
+    it: &mut std::iter::Peekable<std::slice::Iter<Spanned<Token>>>,
+) -> Result<(), ParseError> {
+    match it.next() {
+        None => Ok(()),
+        Some(t) => Err(parse_err(&format!("Unexpected token '{}'", t.item), t.span)),
+    }
+}
+
 fn expect_identifier(
     it: &mut std::iter::Peekable<std::slice::Iter<Spanned<Token>>>,
 ) -> Result<String, ParseError> {
@@ -218,3 +864,246 @@ fn expect_token(
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::colors::*;
+
+    fn var(id: &str) -> Term {
+        Term::Var(id.to_string())
+    }
+
+    fn lam(id: &str, body: Term) -> Term {
+        abs(id.to_string(), body)
+    }
+
+    #[test]
+    fn test_eval() {
+        let id = lam("x", var("x"));
+        let omega = lam("x", app(var("x"), var("x")));
+        let cases = vec![
+            (app(id.clone(), var("y")), "y"),
+            (app(lam("x", var("z")), app(omega.clone(), omega.clone())), "z"),
+            (lam("y", app(id.clone(), var("y"))), "λy.y"),
+            // the bound `y` must be renamed instead of capturing the argument
+            (app(lam("x", lam("y", var("x"))), var("y")), "λy1.y"),
+        ];
+
+        for (term, expected) in cases {
+            let result = eval(&term, Strategy::NormalOrder).unwrap();
+            assert_eq!(result.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn test_eval_diverges() {
+        let omega = lam("x", app(var("x"), var("x")));
+        assert!(eval(&app(omega.clone(), omega), Strategy::NormalOrder).is_err());
+    }
+
+    #[test]
+    fn test_definitions() {
+        let mut ulc = UntypedLambdaCalculus::default();
+        ulc.run("f = λx.g x");
+        ulc.run("let g = λy.y");
+        // `g` was undefined when `f` was defined, so it stays free in `f`
+        assert_eq!(ulc.expand(&var("f")).unwrap().to_string(), "λx.g x");
+        assert_eq!(ulc.expand(&app(var("g"), var("f"))).unwrap().to_string(), "(λy.y) λx.g x");
+        ulc.run("g = λz.z z");
+        assert_eq!(ulc.expand(&var("g")).unwrap().to_string(), "λz.z z");
+    }
+
+    #[test]
+    fn test_prelude() {
+        let mut ulc = UntypedLambdaCalculus::default();
+        ulc.run(":prelude");
+        let list = app(app(var("cons"), var("c1")), app(app(var("cons"), var("c2")), var("nil")));
+        // fix (λf.λn.test (iszro n) (λx.c0) (λx.plus n (f (prd n))) c0) c3
+        let step = app(var("f"), app(var("prd"), var("n")));
+        let sum = lam(
+            "f",
+            lam(
+                "n",
+                app(
+                    app(
+                        app(
+                            app(var("test"), app(var("iszro"), var("n"))),
+                            lam("x", var("c0")),
+                        ),
+                        lam("x", app(app(var("plus"), var("n")), step)),
+                    ),
+                    var("c0"),
+                ),
+            ),
+        );
+        let cases = vec![
+            (parse_str("times c2 c3").unwrap(), "6"),
+            (parse_str("power c2 c3").unwrap(), "8"),
+            (parse_str("sub c5 c2").unwrap(), "3"),
+            (parse_str("equal c2 c2").unwrap(), "true"),
+            (parse_str("and tru fls").unwrap(), "0 / false"),
+            (app(var("prd"), var("c3")), "2"),
+            (app(var("head"), app(var("tail"), list.clone())), "2"),
+            (app(var("isnil"), app(var("tail"), app(var("tail"), list))), "true"),
+            (app(app(var("fix"), sum), var("c3")), "6"),
+        ];
+
+        for (term, expected) in cases {
+            let result = eval(&ulc.expand(&term).unwrap(), Strategy::NormalOrder).unwrap();
+            assert_eq!(prelude::read_back(&result).as_deref(), Some(expected), "{}", term);
+        }
+    }
+
+    #[test]
+    fn test_db() {
+        let mut ulc = UntypedLambdaCalculus::default();
+        let out = ulc.run(":db (λx.x) λy.y z");
+        assert_eq!(out, "Γ = z ↦ 0\nde Bruijn: (λ.0) (λ.0 1)\nnormal form: λ.0 1\nrestored: λx.x z");
+        let out = ulc.run(":db (λx.x x) (λx.x x)");
+        assert!(!out.contains("normal form"), "{}", out);
+        assert!(out.contains("Error: Evaluation did not terminate within 1000 steps\n(λx.x x) λx.x x\n"), "{}", out);
+    }
+
+    #[test]
+    fn test_numeral_limit() {
+        let mut ulc = UntypedLambdaCalculus::default();
+        ulc.run(":prelude");
+        let out = ulc.run(&format!("c{}", prelude::MAX_NUMERAL));
+        assert!(out.contains(&format!("result: {}\n", prelude::MAX_NUMERAL)), "{}", out);
+
+        let past = format!("c{}", prelude::MAX_NUMERAL + 1);
+        let huge = "c99999999999999999999999";
+        let cases = vec![
+            (past.clone(), past.as_str()),
+            (format!("x = {}", past), past.as_str()),
+            (format!(":db {}", past), past.as_str()),
+            (huge.to_string(), huge),
+        ];
+        for (input, numeral) in cases {
+            let out = ulc.run(&input);
+            let message = format!(
+                "Error: Numeral '{}' is larger than the largest numeral c{}\n",
+                numeral,
+                prelude::MAX_NUMERAL
+            );
+            assert!(out.contains(&message), "{}", out);
+        }
+    }
+
+    #[test]
+    fn test_strategies() {
+        let id = lam("x", var("x"));
+        let omega = lam("x", app(var("x"), var("x")));
+        // (λx.y) ((λx.x x) (λx.x x))
+        let discard = app(lam("x", var("y")), app(omega.clone(), omega));
+        // λz.(λx.x) z
+        let under_lambda = lam("z", app(id.clone(), var("z")));
+        // (λx.x) ((λx.x) (λz.(λx.x) z))
+        let nested = app(id.clone(), app(id.clone(), under_lambda.clone()));
+
+        let cases = vec![
+            (Strategy::NormalOrder, &discard, Some("y")),
+            (Strategy::CallByName, &discard, Some("y")),
+            (Strategy::CallByValue, &discard, None),
+            (Strategy::ApplicativeOrder, &discard, None),
+            (Strategy::NormalOrder, &under_lambda, Some("λz.z")),
+            (Strategy::ApplicativeOrder, &under_lambda, Some("λz.z")),
+            (Strategy::CallByName, &under_lambda, Some("λz.(λx.x) z")),
+            (Strategy::CallByValue, &under_lambda, Some("λz.(λx.x) z")),
+            (Strategy::CallByValue, &nested, Some("λz.(λx.x) z")),
+        ];
+
+        for (strategy, term, expected) in cases {
+            let result = eval(term, strategy).ok().map(|t| t.to_string());
+            assert_eq!(result.as_deref(), expected, "{}", strategy);
+        }
+    }
+
+    #[test]
+    fn test_equivalence() {
+        let mut ulc = UntypedLambdaCalculus::default();
+        let cases = vec![
+            (":eq λx.x = λy.y", "alpha-equivalent: yes"),
+            (":eq f x y = f x y", "alpha-equivalent: yes"),
+            (":eq f x y = f (x y)", "alpha-equivalent: no"),
+            (":beq λx.λy.x = λa.(λb.b) λb.a", "alpha-equivalent: no\nbeta-equivalent: yes"),
+            (":beq (λx.x) y = y z", "alpha-equivalent: no\nbeta-equivalent: no"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(ulc.run(input), expected, "{}", input);
+        }
+        assert!(ulc.run(":eq λx.x λy.y").starts_with("Error: Unexpected end of input"));
+        assert!(ulc.run(":eq x = y = z").starts_with("Error: Unexpected token '='"));
+    }
+
+    #[test]
+    fn test_step_derivation() {
+        // λz.y ((λx.x) z) under normal order
+        let term = lam("z", app(var("y"), app(lam("x", var("x")), var("z"))));
+        let step = normal_order_step(&term).unwrap();
+        assert_eq!(
+            step_derivation(&term, &step).to_string(),
+            "λz.y ((λx.x) z) → λz.y z  (E-Abs)\n\
+             \x20 y ((λx.x) z) → y z  (E-App2)\n\
+             \x20   (λx.x) z → z  (E-AppAbs)\n"
+        );
+    }
+
+    #[test]
+    fn test_trace() {
+        // (λx.λy.x) a ((λz.z) b)
+        let term = app(
+            app(lam("x", lam("y", var("x"))), var("a")),
+            app(lam("z", var("z")), var("b")),
+        );
+        assert_eq!(
+            trace(&term, Strategy::NormalOrder),
+            format!(
+                "trace:\n\
+                 \x20 {RED}(λx.λy.x) a{RESET} ((λz.z) b)  {CYAN}E-AppAbs{RESET}\n\
+                 \x20 {RED}(λy.a) ((λz.z) b){RESET}  {CYAN}E-AppAbs{RESET}\n\
+                 \x20 a\n"
+            )
+        );
+        assert_eq!(
+            trace(&term, Strategy::CallByValue),
+            format!(
+                "trace:\n\
+                 \x20 {RED}(λx.λy.x) a{RESET} ((λz.z) b)  {CYAN}E-AppAbs{RESET}\n\
+                 \x20 (λy.a) ({RED}(λz.z) b{RESET})  {CYAN}E-AppAbs{RESET}\n\
+                 \x20 {RED}(λy.a) b{RESET}  {CYAN}E-AppAbs{RESET}\n\
+                 \x20 a\n"
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_and_print() {
+        let cases = vec![
+            ("(λx.x) y", "(λx.x) y"),
+            ("f (g x)", "f (g x)"),
+            ("f g x", "f g x"),
+            ("((f g) x)", "f g x"),
+            ("λx.λy.x y z", "λx.λy.x y z"),
+            ("(λx.x y) z", "(λx.x y) z"),
+            ("f λx.x", "f λx.x"),
+            ("f (λx.x) y", "f (λx.x) y"),
+            ("(f λx.x) y", "f (λx.x) y"),
+            ("x (y λz.z)", "x (y λz.z)"),
+            ("λs.λz.s (s z)", "λs.λz.s (s z)"),
+            ("(λx.x x) (λx.x x)", "(λx.x x) λx.x x"),
+        ];
+
+        for (input, expected) in cases {
+            let term = parse_str(input).unwrap();
+            let printed = term.to_string();
+            assert_eq!(printed, expected);
+            assert_eq!(parse_str(&printed).unwrap(), term, "{}", printed);
+        }
+
+        for input in ["(λx.x", "f )", "λx.", "()"] {
+            assert!(parse_str(input).is_err(), "{}", input);
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use super::abs;
+use super::app;
+use super::fresh_name;
+use super::free_vars;
+use super::Term;
+
+use std::collections::HashSet;
+use std::fmt::{self, Display, Formatter};
+
+/// A term in de Bruijn notation (TAPL ch. 6): each variable is replaced by
+/// the number of binders between its occurrence and the λ that binds it.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum NamelessTerm {
+    Var(usize),
+    Abs(Box<NamelessTerm>),
+    App(Box<NamelessTerm>, Box<NamelessTerm>),
+}
+
+/// A naming context Γ. The last name in the context has index 0.
+pub(super) type Context = Vec<String>;
+
+impl Display for NamelessTerm {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            NamelessTerm::Var(k) => write!(f, "{}", k),
+            NamelessTerm::Abs(t) => write!(f, "λ.{}", t),
+            NamelessTerm::App(t1, t2) => {
+                match **t1 {
+                    NamelessTerm::Abs(_) => write!(f, "({})", t1)?,
+                    _ => write!(f, "{}", t1)?,
+                }
+                match **t2 {
+                    NamelessTerm::Var(_) => write!(f, " {}", t2),
+                    _ => write!(f, " ({})", t2),
+                }
+            }
+        }
+    }
+}
+
+fn nabs(t: NamelessTerm) -> NamelessTerm {
+    NamelessTerm::Abs(Box::new(t))
+}
+
+fn napp(t1: NamelessTerm, t2: NamelessTerm) -> NamelessTerm {
+    NamelessTerm::App(Box::new(t1), Box::new(t2))
+}
+
+/// Builds a naming context holding the free variables of `terms`, in
+/// alphabetical order.
+pub(super) fn free_vars_context(terms: &[&Term]) -> Context {
+    let mut names: Vec<String> = terms
+        .iter()
+        .flat_map(|t| free_vars(t))
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    names.sort();
+    names
+}
+
+/// Converts a named term to its de Bruijn form. Returns `None` if a free
+/// variable of `term` is missing from `ctx`.
+pub(super) fn removenames(ctx: &Context, term: &Term) -> Option<NamelessTerm> {
+    match term {
+        Term::Var(x) => ctx
+            .iter()
+            .rev()
+            .position(|name| name == x)
+            .map(NamelessTerm::Var),
+        Term::Abs(x, body) => {
+            let mut ctx = ctx.clone();
+            ctx.push(x.clone());
+            removenames(&ctx, body).map(nabs)
+        }
+        Term::App(t1, t2) => Some(napp(removenames(ctx, t1)?, removenames(ctx, t2)?)),
+    }
+}
+
+/// Converts a de Bruijn term back to a named term, picking a fresh name for
+/// every binder. Returns `None` if an index is out of range for `ctx`.
+pub(super) fn restorenames(ctx: &Context, term: &NamelessTerm) -> Option<Term> {
+    match term {
+        NamelessTerm::Var(k) => ctx
+            .len()
+            .checked_sub(k + 1)
+            .map(|i| Term::Var(ctx[i].clone())),
+        NamelessTerm::Abs(body) => {
+            let used: HashSet<String> = ctx.iter().cloned().collect();
+            let x = if used.contains("x") {
+                fresh_name("x", &used)
+            } else {
+                "x".to_string()
+            };
+            let mut ctx = ctx.clone();
+            ctx.push(x.clone());
+            restorenames(&ctx, body).map(|body| abs(x, body))
+        }
+        NamelessTerm::App(t1, t2) => Some(app(restorenames(ctx, t1)?, restorenames(ctx, t2)?)),
+    }
+}
+
+/// The `d`-place shift of a term above cutoff `c`, written ↑ᵈ_c(t).
+pub(super) fn shift(d: isize, c: usize, term: &NamelessTerm) -> NamelessTerm {
+    match term {
+        NamelessTerm::Var(k) if *k >= c => NamelessTerm::Var(k.checked_add_signed(d).unwrap()),
+        NamelessTerm::Var(k) => NamelessTerm::Var(*k),
+        NamelessTerm::Abs(body) => nabs(shift(d, c + 1, body)),
+        NamelessTerm::App(t1, t2) => napp(shift(d, c, t1), shift(d, c, t2)),
+    }
+}
+
+/// Substitution of `s` for the variable with index `j`, written [j ↦ s]t.
+pub(super) fn subst(j: usize, s: &NamelessTerm, term: &NamelessTerm) -> NamelessTerm {
+    match term {
+        NamelessTerm::Var(k) if *k == j => s.clone(),
+        NamelessTerm::Var(k) => NamelessTerm::Var(*k),
+        NamelessTerm::Abs(body) => nabs(subst(j + 1, &shift(1, 0, s), body)),
+        NamelessTerm::App(t1, t2) => napp(subst(j, s, t1), subst(j, s, t2)),
+    }
+}
+
+/// Contracts the redex `(λ.body) arg`: ↑⁻¹([0 ↦ ↑¹(arg)] body).
+pub(super) fn beta(body: &NamelessTerm, arg: &NamelessTerm) -> NamelessTerm {
+    shift(-1, 0, &subst(0, &shift(1, 0, arg), body))
+}
+
+/// Two terms are alpha-equivalent iff they have the same de Bruijn form in
+/// a common naming context.
+pub(super) fn alpha_equivalent(t1: &Term, t2: &Term) -> bool {
+    if t1 == t2 {
+        return true;
+    }
+    let ctx = free_vars_context(&[t1, t2]);
+    removenames(&ctx, t1) == removenames(&ctx, t2)
+}
+
+/// A normal order step on de Bruijn terms, see `super::normal_order_step`.
+pub(super) fn normal_order_step(term: &NamelessTerm) -> Option<NamelessTerm> {
+    match term {
+        NamelessTerm::Var(_) => None,
+        NamelessTerm::Abs(body) => normal_order_step(body).map(nabs),
+        NamelessTerm::App(t1, t2) => match &**t1 {
+            NamelessTerm::Abs(body) => Some(beta(body, t2)),
+            _ => normal_order_step(t1)
+                .map(|t1| napp(t1, *t2.clone()))
+                .or_else(|| normal_order_step(t2).map(|t2| napp(*t1.clone(), t2))),
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::untyped_lambda_calculus::substitute;
+
+    fn var(id: &str) -> Term {
+        Term::Var(id.to_string())
+    }
+
+    fn lam(id: &str, body: Term) -> Term {
+        abs(id.to_string(), body)
+    }
+
+    #[test]
+    fn test_removenames() {
+        let ctx = vec!["b".to_string(), "a".to_string()];
+        let cases = vec![
+            (lam("x", lam("y", app(var("x"), var("y")))), "λ.λ.1 0"),
+            (lam("x", app(var("a"), var("x"))), "λ.1 0"),
+            (app(var("b"), lam("x", var("b"))), "1 (λ.2)"),
+            (lam("x", lam("x", var("x"))), "λ.λ.0"),
+        ];
+
+        for (term, expected) in cases {
+            let nameless = removenames(&ctx, &term).unwrap();
+            assert_eq!(nameless.to_string(), expected);
+            let restored = restorenames(&ctx, &nameless).unwrap();
+            assert_eq!(removenames(&ctx, &restored).unwrap(), nameless, "{}", restored);
+        }
+        assert!(removenames(&ctx, &var("c")).is_none());
+    }
+
+    #[test]
+    fn test_beta_agrees_with_named_substitution() {
+        // (λx.λy.x y) applied to y, λz.z and λy.x
+        let body = lam("y", app(var("x"), var("y")));
+        let args = vec![var("y"), lam("z", var("z")), lam("y", var("x"))];
+
+        for arg in args {
+            let named = substitute(&body, "x", &arg);
+            let ctx = free_vars_context(&[&body, &arg, &var("x")]);
+            let mut body_ctx = ctx.clone();
+            body_ctx.push("x".to_string());
+            let nameless = beta(
+                &removenames(&body_ctx, &body).unwrap(),
+                &removenames(&ctx, &arg).unwrap(),
+            );
+            assert_eq!(removenames(&ctx, &named).unwrap(), nameless, "{}", named);
+        }
+    }
+
+    #[test]
+    fn test_alpha_equivalent() {
+        let cases = vec![
+            (lam("x", var("x")), lam("y", var("y")), true),
+            (lam("x", app(var("x"), var("z"))), lam("y", app(var("y"), var("z"))), true),
+            (lam("x", lam("y", var("x"))), lam("y", lam("x", var("y"))), true),
+            (lam("x", lam("y", var("x"))), lam("x", lam("y", var("y"))), false),
+            (lam("x", var("y")), lam("y", var("y")), false),
+            (var("x"), var("y"), false),
+        ];
+
+        for (t1, t2, expected) in cases {
+            assert_eq!(alpha_equivalent(&t1, &t2), expected, "{} {}", t1, t2);
+        }
+    }
+}
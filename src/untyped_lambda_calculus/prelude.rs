@@ -0,0 +1,190 @@
+use super::abs;
+use super::app;
+use super::Term;
+
+/// The Church encodings of TAPL §5.2, in definition order. Each definition
+/// may refer to the ones before it.
+pub(super) fn definitions() -> Vec<(&'static str, Term)> {
+    vec![
+        // booleans
+        ("tru", l("t f", v("t"))),
+        ("fls", l("t f", v("f"))),
+        ("test", l("l m n", ap(&[v("l"), v("m"), v("n")]))),
+        ("and", l("b c", ap(&[v("b"), v("c"), v("fls")]))),
+        ("or", l("b c", ap(&[v("b"), v("tru"), v("c")]))),
+        ("not", l("b", ap(&[v("b"), v("fls"), v("tru")]))),
+        // pairs
+        ("pair", l("f s b", ap(&[v("b"), v("f"), v("s")]))),
+        ("fst", l("p", ap(&[v("p"), v("tru")]))),
+        ("snd", l("p", ap(&[v("p"), v("fls")]))),
+        // numerals
+        (
+            "scc",
+            l("n s z", ap(&[v("s"), ap(&[v("n"), v("s"), v("z")])])),
+        ),
+        (
+            "plus",
+            l(
+                "m n s z",
+                ap(&[v("m"), v("s"), ap(&[v("n"), v("s"), v("z")])]),
+            ),
+        ),
+        (
+            "times",
+            l("m n", ap(&[v("m"), ap(&[v("plus"), v("n")]), numeral(0)])),
+        ),
+        (
+            "power",
+            l("m n", ap(&[v("n"), ap(&[v("times"), v("m")]), numeral(1)])),
+        ),
+        ("iszro", l("m", ap(&[v("m"), l("x", v("fls")), v("tru")]))),
+        (
+            "prd",
+            l(
+                "m",
+                ap(&[
+                    v("fst"),
+                    ap(&[
+                        v("m"),
+                        l(
+                            "p",
+                            ap(&[
+                                v("pair"),
+                                ap(&[v("snd"), v("p")]),
+                                ap(&[v("scc"), ap(&[v("snd"), v("p")])]),
+                            ]),
+                        ),
+                        ap(&[v("pair"), numeral(0), numeral(0)]),
+                    ]),
+                ]),
+            ),
+        ),
+        ("sub", l("m n", ap(&[v("n"), v("prd"), v("m")]))),
+        (
+            "equal",
+            l(
+                "m n",
+                ap(&[
+                    v("and"),
+                    ap(&[v("iszro"), ap(&[v("sub"), v("m"), v("n")])]),
+                    ap(&[v("iszro"), ap(&[v("sub"), v("n"), v("m")])]),
+                ]),
+            ),
+        ),
+        // lists, as their right fold
+        ("nil", l("c n", v("n"))),
+        (
+            "cons",
+            l(
+                "h t c n",
+                ap(&[v("c"), v("h"), ap(&[v("t"), v("c"), v("n")])]),
+            ),
+        ),
+        ("head", l("l", ap(&[v("l"), l("h t", v("h")), v("fls")]))),
+        ("isnil", l("l", ap(&[v("l"), l("h t", v("fls")), v("tru")]))),
+        (
+            "tail",
+            l(
+                "l",
+                ap(&[
+                    v("fst"),
+                    ap(&[
+                        v("l"),
+                        l(
+                            "x p",
+                            ap(&[
+                                v("pair"),
+                                ap(&[v("snd"), v("p")]),
+                                ap(&[v("cons"), v("x"), ap(&[v("snd"), v("p")])]),
+                            ]),
+                        ),
+                        ap(&[v("pair"), v("nil"), v("nil")]),
+                    ]),
+                ]),
+            ),
+        ),
+        // recursion
+        ("fix", l("f", ap(&[z_half(), z_half()]))),
+        ("Z", v("fix")),
+        ("Y", l("f", ap(&[y_half(), y_half()]))),
+    ]
+}
+
+/// λx.f (λy.x x y), the call-by-value fixed point combinator's halves.
+fn z_half() -> Term {
+    l("x", ap(&[v("f"), l("y", ap(&[v("x"), v("x"), v("y")]))]))
+}
+
+/// λx.f (x x)
+fn y_half() -> Term {
+    l("x", ap(&[v("f"), ap(&[v("x"), v("x")])]))
+}
+
+/// The Church numeral cₙ = λs.λz.sⁿ z.
+pub(super) fn numeral(n: usize) -> Term {
+    let body = (0..n).fold(v("z"), |t, _| app(v("s"), t));
+    l("s z", body)
+}
+
+/// The largest numeral a name such as `c3` stands for. `numeral` nests its
+/// body as deep as the numeral is large, and deeper terms overflow the stack
+/// of the functions that walk them.
+pub(super) const MAX_NUMERAL: usize = 1000;
+
+/// Parses a numeral name such as `c3`. Numbers too large for a `usize` are
+/// read as `usize::MAX`, so they are still recognised as numerals.
+pub(super) fn numeral_name(id: &str) -> Option<usize> {
+    let digits = id.strip_prefix('c')?;
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some(digits.parse().unwrap_or(usize::MAX))
+}
+
+/// Reads a normal form back as a Church numeral and/or boolean, e.g.
+/// `λs.λz.s (s z)` as `2`. Note that c₀ and fls are the same term.
+pub(super) fn read_back(term: &Term) -> Option<String> {
+    let (x, y, body) = match term {
+        Term::Abs(x, t) => match &**t {
+            Term::Abs(y, body) if x != y => (x, y, body),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    match &**body {
+        Term::Var(b) if b == x => return Some("true".to_string()),
+        Term::Var(b) if b == y => return Some("0 / false".to_string()),
+        _ => {}
+    }
+    let mut n = 0;
+    let mut t = &**body;
+    loop {
+        match t {
+            Term::App(s, rest) if **s == Term::Var(x.clone()) => {
+                n += 1;
+                t = rest;
+            }
+            Term::Var(z) if z == y => return Some(n.to_string()),
+            _ => return None,
+        }
+    }
+}
+
+fn v(id: &str) -> Term {
+    Term::Var(id.to_string())
+}
+
+/// `l("x y", t)` is `λx.λy.t`.
+fn l(params: &str, body: Term) -> Term {
+    params
+        .split_whitespace()
+        .rev()
+        .fold(body, |t, x| abs(x.to_string(), t))
+}
+
+/// Left-associative application of `terms`.
+fn ap(terms: &[Term]) -> Term {
+    let mut it = terms.iter().cloned();
+    let first = it.next().unwrap();
+    it.fold(first, app)
+}